@@ -0,0 +1,72 @@
+//! Carving a grid of terrain chunks one after another versus on the `ComputeTaskPool`.
+//!
+//! Run with `cargo bench --bench carve`.
+
+#![allow(clippy::needless_return)]
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/carve.rs"]
+mod carve;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/polygon.rs"]
+mod polygon;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/polygon_transform_bundle.rs"]
+mod polygon_transform_bundle;
+
+use bevy::math::{Quat, Vec2, Vec3};
+use bevy::prelude::{Entity, Transform};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use crate::carve::{Cut, carve, carve_parallel};
+use crate::polygon::Polygon;
+use crate::polygon_transform_bundle::{PolygonTransformBundle, Precision};
+
+const CHUNK_SIZE: f32 = 8.;
+const CHUNK_SEGMENTS: usize = 256;
+
+/// A `side` by `side` grid of round chunks, each with `CHUNK_SEGMENTS` vertices.
+fn chunks(side: usize) -> Vec<(Entity, PolygonTransformBundle)> {
+    let mut chunks = vec![];
+    for y in 0..side {
+        for x in 0..side {
+            let position = CHUNK_SIZE * Vec2::new(x as f32, y as f32);
+            chunks.push((
+                Entity::from_raw((y * side + x) as u32),
+                PolygonTransformBundle::from((
+                    Polygon::circle(0.5, CHUNK_SEGMENTS),
+                    Transform::from_translation(position.extend(0.)).with_scale(Vec3::splat(CHUNK_SIZE)),
+                )),
+            ));
+        }
+    }
+    return chunks;
+}
+
+/// A tilted slab cutting across the whole grid, clipping the side of every chunk in its path.
+fn cuts(side: usize) -> Vec<Cut> {
+    let length = CHUNK_SIZE * side as f32 * 2.;
+    let center = Vec2::splat(CHUNK_SIZE * (side as f32 - 1.) / 2.);
+    let transform = Transform::from_translation(center.extend(0.))
+        .with_rotation(Quat::from_rotation_z(0.5));
+    let mouth = PolygonTransformBundle::from((Polygon::rectangle(Vec2::new(length, CHUNK_SIZE / 2.)), transform));
+    let aabb = mouth.polygon.to_global_space(&transform).aabb();
+    return vec![Cut { carver: Entity::from_raw(u32::MAX), mouth, aabb }];
+}
+
+fn bench_carve(c: &mut Criterion) {
+    let mut group = c.benchmark_group("carve");
+    for side in [1, 2, 4, 8] {
+        let chunks = chunks(side);
+        let cuts = cuts(side);
+        group.bench_with_input(BenchmarkId::new("sequential", side * side), &chunks, |b, chunks| {
+            b.iter(|| chunks.iter().cloned().map(|(entity, bundle)| carve(entity, bundle, &cuts, &Precision::default())).collect::<Vec<_>>());
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", side * side), &chunks, |b, chunks| {
+            b.iter(|| carve_parallel(chunks.clone(), &cuts, &Precision::default()));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_carve);
+criterion_main!(benches);
@@ -0,0 +1,101 @@
+//! Throughput of the polygon operations terrain carving is built on, headless.
+//!
+//! Run with `cargo bench --bench polygon`.
+
+#![allow(clippy::needless_return)]
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/polygon.rs"]
+mod polygon;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/polygon_transform_bundle.rs"]
+mod polygon_transform_bundle;
+
+use std::f32::consts::TAU;
+use bevy::math::{Quat, Vec2, Vec3};
+use bevy::prelude::Transform;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use crate::polygon::Polygon;
+use crate::polygon_transform_bundle::PolygonTransformBundle;
+
+const VERTEX_COUNTS: [usize; 5] = [16, 64, 256, 1024, 4096];
+const CUT_COUNTS: [usize; 4] = [1, 4, 16, 64];
+
+/// A disc of terrain 16 units across with `segments` vertices, stored as a unit circle under a
+/// translated, rotated and scaled transform so every carve pays for the space conversions.
+fn terrain(segments: usize) -> PolygonTransformBundle {
+    return PolygonTransformBundle::from((
+        Polygon::circle(0.5, segments),
+        Transform::from_xyz(3., -2., 0.)
+            .with_rotation(Quat::from_rotation_z(0.3))
+            .with_scale(Vec3::splat(16.)),
+    ));
+}
+
+/// `count` mouth-sized cuts stepping around the rim of `terrain`, each biting into it.
+fn cuts(count: usize) -> Vec<PolygonTransformBundle> {
+    return (0..count)
+        .map(|index| {
+            let angle = TAU * index as f32 / count as f32;
+            let center = Vec2::new(3., -2.) + 8. * Vec2::from_angle(angle);
+            PolygonTransformBundle::from((
+                Polygon::rectangle(Vec2::splat(2.)),
+                Transform::from_translation(center.extend(0.)).with_rotation(Quat::from_rotation_z(angle)),
+            ))
+        })
+        .collect();
+}
+
+/// Applies each cut to whatever the previous ones left, the way `carve` does for one entity.
+fn carve(terrain: PolygonTransformBundle, cuts: &[PolygonTransformBundle]) -> Vec<PolygonTransformBundle> {
+    let mut pieces = vec![terrain];
+    for cut in cuts {
+        pieces = pieces.into_iter().flat_map(|piece| piece.sink(cut)).collect();
+    }
+    return pieces;
+}
+
+fn bench_space(c: &mut Criterion) {
+    let mut group = c.benchmark_group("space");
+    for vertex_count in VERTEX_COUNTS {
+        let terrain = terrain(vertex_count);
+        let global = terrain.polygon.to_global_space(&terrain.transform);
+        group.throughput(Throughput::Elements(vertex_count as u64));
+        group.bench_with_input(BenchmarkId::new("to_global_space", vertex_count), &terrain, |b, terrain| {
+            b.iter(|| terrain.polygon.to_global_space(&terrain.transform));
+        });
+        group.bench_with_input(BenchmarkId::new("to_local_space", vertex_count), &global, |b, global| {
+            b.iter(|| global.to_local_space(terrain.transform));
+        });
+    }
+    group.finish();
+}
+
+fn bench_sink_vertices(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sink/vertices");
+    let cut = &cuts(1)[0];
+    for vertex_count in VERTEX_COUNTS {
+        let terrain = terrain(vertex_count);
+        group.throughput(Throughput::Elements(vertex_count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(vertex_count), &terrain, |b, terrain| {
+            b.iter(|| terrain.clone().sink(cut));
+        });
+    }
+    group.finish();
+}
+
+fn bench_sink_cuts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sink/cuts");
+    let terrain = terrain(256);
+    for cut_count in CUT_COUNTS {
+        let cuts = cuts(cut_count);
+        group.throughput(Throughput::Elements(cut_count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(cut_count), &cuts, |b, cuts| {
+            b.iter(|| carve(terrain.clone(), cuts));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_space, bench_sink_vertices, bench_sink_cuts);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+use std::{fmt, io};
+use bevy::render::texture::TextureError;
+
+/// Failure reading or writing one of the game's data files: levels, masks and tuning.
+#[derive(Debug)]
+pub(crate) enum AssetError {
+    Io(io::Error),
+    Format(ron::Error),
+    Image(String),
+    /// Parsed, but describes something the game can't use.
+    Invalid(String),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Io(error) => write!(f, "could not access asset file: {error}"),
+            AssetError::Format(error) => write!(f, "could not parse asset file: {error}"),
+            AssetError::Image(error) => write!(f, "could not decode image: {error}"),
+            AssetError::Invalid(reason) => write!(f, "invalid asset: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+impl From<io::Error> for AssetError {
+    fn from(error: io::Error) -> Self {
+        AssetError::Io(error)
+    }
+}
+
+impl From<ron::Error> for AssetError {
+    fn from(error: ron::Error) -> Self {
+        AssetError::Format(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for AssetError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        AssetError::Format(error.code)
+    }
+}
+
+impl From<TextureError> for AssetError {
+    fn from(error: TextureError) -> Self {
+        AssetError::Image(error.to_string())
+    }
+}
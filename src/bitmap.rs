@@ -0,0 +1,480 @@
+use std::{fs, io};
+use std::path::Path;
+use bevy::asset::{Assets, Handle};
+use bevy::log::warn;
+use bevy::math::{Rect, Vec2};
+use bevy::prelude::{Color, Commands, Component, DetectChangesMut, Entity, EventWriter, Image, Query, Sprite, SpriteBundle, Transform};
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::{CompressedImageFormats, ImageSampler, ImageType};
+use serde::{Deserialize, Serialize};
+use crate::asset::AssetError;
+use crate::carve::Cut;
+use crate::contour::{Outlined, contours};
+use crate::level::TerrainMaterial;
+use crate::polygon::Polygon;
+use crate::TerrainCarved;
+
+/// Side of a `BitmapTile`, in pixels.
+const TILE_SIZE: usize = 64;
+/// Pixels each `BitmapTile` keeps of its neighbours' edges, so its outline carries on across the
+/// seam instead of closing off where the tile ends.
+const APRON: usize = 1;
+/// How far, in pixels, traced outlines may stray from the pixel edges to save vertices.
+const TRACE_TOLERANCE: f32 = 0.75;
+
+/// Mask traced into polygon terrain for a new game when there's no level file.
+pub(crate) const TERRAIN_MASK_PATH: &str = "assets/terrain.png";
+
+/// Which pixels of an image are solid, row 0 at the top as in the image.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(into = "MaskRows", try_from = "MaskRows")]
+pub(crate) struct Mask {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) solid: Vec<bool>,
+}
+
+/// A `Mask` as saved and sent: its rows top first, `#` for solid pixels and `.` for empty ones.
+#[derive(Deserialize, Serialize)]
+struct MaskRows(Vec<String>);
+
+impl From<Mask> for MaskRows {
+    fn from(mask: Mask) -> Self {
+        MaskRows(mask.solid.chunks(mask.width.max(1))
+            .map(|row| row.iter().map(|solid| if *solid { '#' } else { '.' }).collect())
+            .collect())
+    }
+}
+
+impl TryFrom<MaskRows> for Mask {
+    type Error = String;
+
+    fn try_from(rows: MaskRows) -> Result<Self, Self::Error> {
+        let width = rows.0.first().map_or(0, |row| row.len());
+        let mut solid = Vec::with_capacity(width * rows.0.len());
+        for row in rows.0.iter() {
+            if row.len() != width {
+                return Err(format!("mask rows of {} and {width} pixels", row.len()));
+            }
+            for pixel in row.chars() {
+                solid.push(match pixel {
+                    '#' => true,
+                    '.' => false,
+                    pixel => return Err(format!("mask pixel {pixel:?}")),
+                });
+            }
+        }
+        return Ok(Mask { width, height: rows.0.len(), solid });
+    }
+}
+
+impl Mask {
+    /// Decodes a PNG. Images with any transparency are solid where at least half opaque, like
+    /// classic Worms levels drawn over an empty sky; opaque images are solid where at least half
+    /// bright, like a black and white mask.
+    pub(crate) fn from_png(bytes: &[u8]) -> Result<Mask, AssetError> {
+        let image = Image::from_buffer(bytes, ImageType::Extension("png"), CompressedImageFormats::NONE, false, ImageSampler::Default)?;
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let channels = match image.texture_descriptor.format {
+            TextureFormat::R8Unorm => 1,
+            TextureFormat::Rg8Unorm => 2,
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => 4,
+            format => return Err(AssetError::Image(format!("unsupported pixel format {format:?}"))),
+        };
+        // Every format but plain luminance ends in alpha.
+        let color_channels = if channels == 1 { 1 } else { channels - 1 };
+        let pixels: Vec<&[u8]> = image.data.chunks_exact(channels).collect();
+        let alpha = |pixel: &[u8]| if channels == 1 { u8::MAX } else { pixel[channels - 1] };
+        let brightness = |pixel: &[u8]| pixel[..color_channels].iter().map(|channel| *channel as usize).sum::<usize>() / color_channels;
+
+        let transparent = pixels.iter().any(|pixel| alpha(pixel) < u8::MAX);
+        let solid = pixels.iter()
+            .map(|pixel| if transparent { alpha(pixel) >= 128 } else { brightness(pixel) >= 128 })
+            .collect();
+        return Ok(Mask { width, height, solid });
+    }
+
+    pub(crate) fn read(path: impl AsRef<Path>) -> Result<Mask, AssetError> {
+        return Mask::from_png(&fs::read(path)?);
+    }
+
+    /// Reads the mask file, if there is one. Failures other than a missing file are logged.
+    pub(crate) fn load(path: impl AsRef<Path>) -> Option<Mask> {
+        let path = path.as_ref();
+        return match Mask::read(path) {
+            Ok(mask) => Some(mask),
+            Err(AssetError::Io(error)) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => {
+                warn!("{}: {error}", path.display());
+                None
+            }
+        };
+    }
+
+    pub(crate) fn is_solid(&self, x: usize, row: usize) -> bool {
+        return self.solid[row * self.width + x];
+    }
+
+    /// Outlines of the solid pixels along the edges they share with empty ones, in pixels with the
+    /// origin at the image's center and y up. Holes are bridged into the outline around them.
+    pub(crate) fn contours(&self) -> Vec<Polygon> {
+        let (width, height) = (self.width, self.height);
+        if width == 0 || height == 0 {
+            return vec![];
+        }
+        let offset = Vec2::new(0.5 - width as f32 / 2., 0.5 - height as f32 / 2.);
+        // Traced through pixel centers, which puts the outline halfway between them.
+        return contours(width - 1, height - 1, |x, y| if self.is_solid(x, height - 1 - y) { -1. } else { 1. })
+            .into_iter()
+            .map(|outline| Polygon::from(outline.vertices.iter()
+                .map(|vertex| *vertex + offset)
+                .collect::<Vec<Vec2>>()))
+            .collect();
+    }
+
+    /// `contours` simplified for use as polygon terrain.
+    pub(crate) fn trace(&self) -> Vec<Polygon> {
+        return self.contours().into_iter()
+            .map(|outline| outline.simplify(TRACE_TOLERANCE))
+            .filter(|outline| outline.vertices.len() >= 3)
+            .collect();
+    }
+
+    /// The `width` by `height` pixels whose top left is at `(x, row)`, empty past the edges.
+    fn crop(&self, x: isize, row: isize, width: usize, height: usize) -> Mask {
+        let solid = (row..row + height as isize)
+            .flat_map(|row| (x..x + width as isize).map(move |x| (x, row)))
+            .map(|(x, row)| {
+                let inside = (0..self.width as isize).contains(&x) && (0..self.height as isize).contains(&row);
+                return inside && self.is_solid(x as usize, row as usize);
+            })
+            .collect();
+        return Mask { width, height, solid };
+    }
+}
+
+/// A square of pixel terrain, drawn as a texture it keeps up to date as it's carved. Its local
+/// space measures one unit per pixel with the origin at the tile's center.
+#[derive(Component)]
+pub(crate) struct BitmapTile {
+    /// The tile's pixels surrounded by an `APRON` of its neighbours', which are carved by the same
+    /// cuts and so stay in step with them.
+    mask: Mask,
+    image: Handle<Image>,
+}
+
+impl BitmapTile {
+    /// The tile's pixels, apron included.
+    pub(crate) fn mask(&self) -> &Mask {
+        return &self.mask;
+    }
+
+    /// Replaces the tile's pixels, apron included, and redraws its texture in `color` to match.
+    pub(crate) fn set_mask(&mut self, mask: Mask, color: Color, images: &mut Assets<Image>) {
+        self.mask = mask;
+        if let Some(image) = images.get_mut(&self.image) {
+            *image = texture(&self.interior(), color);
+        }
+    }
+
+    /// The tile's pixels without the apron.
+    fn interior(&self) -> Mask {
+        let apron = APRON as isize;
+        return self.mask.crop(apron, apron, self.mask.width - 2 * APRON, self.mask.height - 2 * APRON);
+    }
+
+    /// Size of the tile itself, without the apron.
+    fn size(&self) -> Vec2 {
+        return Vec2::new((self.mask.width - 2 * APRON) as f32, (self.mask.height - 2 * APRON) as f32);
+    }
+
+    /// Index in the tile's texture of `mask` pixel `index`, unless it belongs to the apron.
+    fn image_index(&self, index: usize) -> Option<usize> {
+        let (x, row) = (index % self.mask.width, index / self.mask.width);
+        let (width, height) = (self.mask.width - 2 * APRON, self.mask.height - 2 * APRON);
+        if !(APRON..APRON + width).contains(&x) || !(APRON..APRON + height).contains(&row) {
+            return None;
+        }
+        return Some((row - APRON) * width + x - APRON);
+    }
+
+    fn center(&self, x: usize, row: usize) -> Vec2 {
+        return Vec2::new(
+            x as f32 + 0.5 - self.mask.width as f32 / 2.,
+            self.mask.height as f32 / 2. - row as f32 - 0.5,
+        );
+    }
+
+    /// Clears every solid pixel, apron included, whose center lies inside `cutter`, given in the
+    /// tile's local space, returning their indices in `mask`.
+    fn carve(&mut self, cutter: &Polygon) -> Vec<usize> {
+        let aabb = cutter.aabb();
+        let half_size = Vec2::new(self.mask.width as f32, self.mask.height as f32) / 2.;
+        let min_x = (aabb.min.x + half_size.x).floor().max(0.) as usize;
+        let max_x = ((aabb.max.x + half_size.x).ceil().max(0.) as usize).min(self.mask.width);
+        let min_row = (half_size.y - aabb.max.y).floor().max(0.) as usize;
+        let max_row = ((half_size.y - aabb.min.y).ceil().max(0.) as usize).min(self.mask.height);
+
+        let mut cleared = vec![];
+        for row in min_row..max_row {
+            for x in min_x..max_x {
+                let index = row * self.mask.width + x;
+                if self.mask.solid[index] && cutter.contains(self.center(x, row)) {
+                    self.mask.solid[index] = false;
+                    cleared.push(index);
+                }
+            }
+        }
+        return cleared;
+    }
+}
+
+impl Outlined for BitmapTile {
+    fn contours(&self) -> Vec<Polygon> {
+        // Traced with the apron, then cut back to the tile, so outlines meet their neighbours'
+        // along the seam instead of each bevelling off half a pixel short of it.
+        let tile = Rect::from_center_size(Vec2::ZERO, self.size());
+        return self.mask.contours().into_iter()
+            .map(|outline| outline.clip(tile))
+            .filter(|outline| outline.vertices.len() >= 3 && outline.area() > 0.)
+            .collect();
+    }
+}
+
+/// Texture showing the solid pixels of `mask` in `color` over transparency.
+fn texture(mask: &Mask, color: Color) -> Image {
+    let solid_color = color.as_rgba_u8();
+    let data = mask.solid.iter()
+        .flat_map(|solid| if *solid { solid_color } else { [0; 4] })
+        .collect();
+    let mut image = Image::new(
+        Extent3d { width: mask.width as u32, height: mask.height as u32, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    image.sampler = ImageSampler::nearest();
+    return image;
+}
+
+/// Spawns `mask` as `BitmapTile`s, centered on `transform` and with pixels `transform.scale` in
+/// size, skipping tiles with nothing solid in them.
+pub(crate) fn spawn_bitmap_terrain(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    mask: &Mask,
+    transform: Transform,
+    material: TerrainMaterial,
+) {
+    let apron = APRON as isize;
+    for row in (0..mask.height).step_by(TILE_SIZE) {
+        for x in (0..mask.width).step_by(TILE_SIZE) {
+            let (width, height) = (TILE_SIZE.min(mask.width - x), TILE_SIZE.min(mask.height - row));
+            if !mask.crop(x as isize, row as isize, width, height).solid.contains(&true) {
+                continue;
+            }
+            let tile_mask = mask.crop(x as isize - apron, row as isize - apron, width + 2 * APRON, height + 2 * APRON);
+
+            // From the image's center to the tile's, in pixels.
+            let offset = Vec2::new(
+                x as f32 + width as f32 / 2. - mask.width as f32 / 2.,
+                mask.height as f32 / 2. - row as f32 - height as f32 / 2.,
+            );
+            let tile_transform = Transform {
+                translation: transform.transform_point(offset.extend(0.)),
+                ..transform
+            };
+            spawn_bitmap_tile(commands, images, tile_mask, tile_transform, material);
+        }
+    }
+}
+
+/// Spawns a single tile of `mask`, apron included, whose center is at `transform`.
+pub(crate) fn spawn_bitmap_tile(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    mask: Mask,
+    transform: Transform,
+    material: TerrainMaterial,
+) -> Entity {
+    let tile = BitmapTile { mask, image: Handle::default() };
+    let image = images.add(texture(&tile.interior(), material.color()));
+    return commands
+        .spawn(SpriteBundle {
+            sprite: Sprite { custom_size: Some(tile.size()), ..Sprite::default() },
+            texture: image.clone(),
+            transform,
+            ..SpriteBundle::default()
+        })
+        .insert(BitmapTile { image, ..tile })
+        .insert(material)
+        .id();
+}
+
+/// Applies this frame's cuts to every bitmap tile they reach, clearing the carved pixels from the
+/// tile's texture as well as its mask.
+pub(crate) fn carve_bitmap(
+    cuts: &[Cut],
+    tile_query: &mut Query<(Entity, &mut BitmapTile, &Transform)>,
+    images: &mut Assets<Image>,
+    carved_events: &mut EventWriter<TerrainCarved>,
+) {
+    for (entity, mut tile, transform) in tile_query.iter_mut() {
+        // Covering the apron, whose pixels must see every cut their own tile does.
+        let size = Vec2::new(tile.mask.width as f32, tile.mask.height as f32) * transform.scale.truncate();
+        let aabb = Rect::from_center_size(transform.translation.truncate(), size);
+        let tile_aabb = Rect::from_center_size(transform.translation.truncate(), tile.size() * transform.scale.truncate());
+        for cut in cuts.iter() {
+            if cut.aabb.intersect(aabb).is_empty() {
+                continue;
+            }
+            let cutter = cut.mouth.polygon.to_global_space(&cut.mouth.transform).to_local_space(*transform);
+            let cleared = tile.bypass_change_detection().carve(&cutter);
+            if cleared.is_empty() {
+                continue;
+            }
+            tile.set_changed();
+            // Pixels in the apron belong to a neighbour, which draws and reports them itself.
+            let cleared: Vec<usize> = cleared.into_iter().filter_map(|index| tile.image_index(index)).collect();
+            if cleared.is_empty() {
+                continue;
+            }
+            if let Some(image) = images.get_mut(&tile.image) {
+                for index in cleared.iter() {
+                    image.data[4 * index..4 * index + 4].fill(0);
+                }
+            }
+            carved_events.send(TerrainCarved {
+                entity,
+                region: cut.aabb.intersect(tile_aabb),
+                removed_area: cleared.len() as f32 * transform.scale.x * transform.scale.y,
+                carver: cut.carver,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::Handle;
+    use bevy::math::{Rect, Vec2};
+    use bevy::prelude::Transform;
+    use crate::bitmap::{APRON, BitmapTile, Mask};
+    use crate::contour::Outlined;
+    use crate::polygon::Polygon;
+    use crate::polygon_transform_bundle::PolygonTransformBundle;
+
+    /// A tile of `width` by `height` pixels, with `solid` given for its apron too, at -1 and
+    /// `width` or `height`.
+    fn tile(width: usize, height: usize, solid: impl Fn(isize, isize) -> bool) -> BitmapTile {
+        let (width, height) = (width + 2 * APRON, height + 2 * APRON);
+        let apron = APRON as isize;
+        let solid = (0..height).flat_map(|row| (0..width).map(move |x| (x, row)))
+            .map(|(x, row)| solid(x as isize - apron, row as isize - apron))
+            .collect();
+        return BitmapTile { mask: Mask { width, height, solid }, image: Handle::default() };
+    }
+
+    #[test]
+    fn test_contours() {
+        let actual = tile(4, 2, |x, row| (0..3).contains(&x) && (0..2).contains(&row)).contours();
+
+        assert_eq!(actual.len(), 1);
+        // Marching squares bevels each corner pixel by an eighth of its area.
+        assert!((actual[0].area() - 5.5).abs() < 0.0001);
+        assert!(actual[0].contains(Vec2::new(-1.5, 0.5)));
+        assert!(!actual[0].contains(Vec2::new(1.5, 0.5)));
+    }
+
+    #[test]
+    fn test_contours_seamless() {
+        // Solid on every side, as in the middle of a level: the outline is the whole tile.
+        let actual = tile(4, 4, |_, _| true).contours();
+
+        assert_eq!(actual.len(), 1);
+        assert!((actual[0].area() - 16.).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_carve() {
+        let mut tile = tile(8, 8, |_, _| true);
+
+        let cleared = tile.carve(&Polygon::rectangle(Vec2::splat(2.)));
+
+        assert_eq!(cleared.len(), 4);
+        assert!(!tile.mask.is_solid(APRON + 3, APRON + 3));
+        assert!(tile.mask.is_solid(APRON + 2, APRON + 3));
+        assert_eq!(tile.image_index(cleared[0]), Some(3 * 8 + 3));
+        assert!(tile.carve(&Polygon::rectangle(Vec2::splat(2.))).is_empty());
+    }
+
+    #[test]
+    fn test_carve_apron() {
+        let mut tile = tile(4, 4, |_, _| true);
+
+        // Reaches half a pixel past the tile's right edge, over the centers of the apron there.
+        let cleared = tile.carve(&Polygon::from(Rect::new(2.25, -3., 3., 3.)));
+
+        assert_eq!(cleared.len(), 6);
+        assert!(cleared.iter().all(|index| tile.image_index(*index).is_none()));
+    }
+
+    #[test]
+    fn test_mask_round_trip() {
+        let expected = tile(3, 2, |x, row| x == row).mask;
+
+        let source = ron::to_string(&expected).unwrap();
+        let actual: Mask = ron::from_str(&source).unwrap();
+
+        assert_eq!(actual, expected);
+        assert!(source.contains("\"#....\""));
+        assert!(ron::from_str::<Mask>("([\"#.\", \"#\"])").is_err());
+    }
+
+    #[test]
+    fn test_trace() {
+        // A ring: a 6 by 6 square with a 2 by 2 hole in the middle.
+        let solid = (0..6).flat_map(|row| (0..6).map(move |x| !(2..4).contains(&x) || !(2..4).contains(&row))).collect();
+        let mask = Mask { width: 6, height: 6, solid };
+
+        let actual = mask.trace();
+
+        assert_eq!(actual.len(), 1);
+        assert!(actual[0].vertices.len() < mask.contours()[0].vertices.len());
+        assert!(!actual[0].contains(Vec2::ZERO));
+        assert!(actual[0].contains(Vec2::new(-2.5, 0.)));
+        assert!(actual[0].contains(Vec2::new(0., -2.5)));
+    }
+
+    #[test]
+    fn test_contours_empty_mask() {
+        let mask = Mask { width: 0, height: 0, solid: vec![] };
+
+        assert!(mask.contours().is_empty());
+        assert!(mask.trace().is_empty());
+    }
+
+    #[test]
+    fn test_sink_through_bridge_and_hole() {
+        // The ring from `test_trace`, whose hole is bridged to the right side of the outline.
+        let solid = (0..6).flat_map(|row| (0..6).map(move |x| !(2..4).contains(&x) || !(2..4).contains(&row))).collect();
+        let mask = Mask { width: 6, height: 6, solid };
+        // From the middle of the hole out past the right side, across the slit.
+        let mouth = PolygonTransformBundle::from((Polygon::from(Rect::new(0., -1.5, 4., 1.5)), Transform::default()));
+
+        for outline in [mask.contours().remove(0), mask.trace().remove(0)] {
+            let terrain = PolygonTransformBundle::from((outline.clone(), Transform::default()));
+
+            let actual = terrain.sink(&mouth);
+
+            assert_eq!(actual.len(), 1);
+            let carved = &actual[0].polygon;
+            assert!(carved.area() < outline.area() - 4.);
+            assert!(!carved.contains(Vec2::new(2., 0.)));
+            assert!(!carved.contains(Vec2::new(-0.5, 0.)));
+            assert!(carved.contains(Vec2::new(-2., 0.)));
+            assert!(carved.contains(Vec2::new(2., 2.)));
+            assert!(carved.contains(Vec2::new(2., -2.)));
+        }
+    }
+}
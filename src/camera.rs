@@ -0,0 +1,189 @@
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::math::{Rect, UVec2, Vec2};
+use bevy::prelude::{Camera, Camera2d, Camera2dBundle, Commands, Component, EventReader, GlobalTransform, OrthographicProjection, Query, Res, Resource, Time, Transform, With, Without};
+use bevy::render::camera::Viewport;
+use bevy::utils::default;
+use bevy::window::{PrimaryWindow, Window};
+use crate::{LocalPlayers, Player};
+use crate::tuning::Tuning;
+
+/// World-space rectangle the camera is never allowed to show past.
+#[derive(Resource)]
+pub(crate) struct LevelBounds(pub(crate) Rect);
+
+#[derive(Component)]
+pub(crate) struct CameraFollow {
+    /// How quickly the camera catches up to the player, in 1/seconds.
+    pub(crate) smoothing: f32,
+    pub(crate) min_scale: f32,
+    pub(crate) max_scale: f32,
+    /// Fraction the scale changes per mouse wheel line.
+    pub(crate) zoom_step: f32,
+}
+
+impl Default for CameraFollow {
+    fn default() -> Self {
+        CameraFollow {
+            smoothing: 8.,
+            min_scale: 1. / 64.,
+            max_scale: 1. / 4.,
+            zoom_step: 0.1,
+        }
+    }
+}
+
+/// Camera following the local player with the same index, in its own slice of the window.
+#[derive(Component)]
+pub(crate) struct PlayerCamera(pub(crate) usize);
+
+pub(crate) fn startup_camera(mut commands: Commands, tuning: Res<Tuning>, local_players: Res<LocalPlayers>) {
+    for index in 0..local_players.0 {
+        commands.spawn(Camera2dBundle {
+            camera: Camera {
+                order: index as isize,
+                ..default()
+            },
+            // Clearing clears the whole window, not just the viewport, so only the first camera
+            // may or it would wipe out the views drawn before it.
+            camera_2d: Camera2d {
+                clear_color: if index == 0 { ClearColorConfig::Default } else { ClearColorConfig::None },
+            },
+            projection: OrthographicProjection {
+                scale: tuning.camera_scale,
+                ..default()
+            },
+            ..default()
+        })
+            .insert(CameraFollow::default())
+            .insert(PlayerCamera(index));
+    }
+}
+
+/// Runs after physics writeback and before transform propagation so that the `GlobalTransform`
+/// used by `viewport_to_world_2d` next frame is the one the frame was rendered with.
+pub(crate) fn update_camera_follow(
+    time: Res<Time>,
+    bounds: Res<LevelBounds>,
+    player_query: Query<(&Player, &Transform)>,
+    mut camera_query: Query<(&PlayerCamera, &CameraFollow, &OrthographicProjection, &mut Transform), Without<Player>>,
+) {
+    for (player_camera, follow, projection, mut camera_transform) in camera_query.iter_mut() {
+        let Some((_, player_transform)) = player_query.iter().find(|(player, _)| player.0 == player_camera.0) else {
+            continue;
+        };
+
+        let t = 1. - (-follow.smoothing * time.delta_seconds()).exp();
+        let center = camera_transform.translation.truncate()
+            .lerp(player_transform.translation.truncate(), t);
+        let center = clamp_to_bounds(center, projection.area.half_size(), bounds.0);
+        camera_transform.translation = center.extend(camera_transform.translation.z);
+    }
+}
+
+/// Zooms every player's camera together.
+pub(crate) fn update_camera_zoom(
+    mut wheel_events: EventReader<MouseWheel>,
+    mut camera_query: Query<(&CameraFollow, &mut OrthographicProjection)>,
+) {
+    for wheel_event in wheel_events.read() {
+        let lines = match wheel_event.unit {
+            MouseScrollUnit::Line => wheel_event.y,
+            MouseScrollUnit::Pixel => wheel_event.y / 16.,
+        };
+        for (follow, mut projection) in camera_query.iter_mut() {
+            projection.scale = zoom(projection.scale, lines, follow);
+        }
+    }
+}
+
+/// Splits the window into side-by-side columns, one per player camera.
+pub(crate) fn update_camera_viewports(
+    local_players: Res<LocalPlayers>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<(&PlayerCamera, &mut Camera)>,
+) {
+    let window = window_query.single();
+    let window_size = UVec2::new(window.physical_width(), window.physical_height());
+
+    for (player_camera, mut camera) in camera_query.iter_mut() {
+        let split = (local_players.0 > 1).then(|| split_viewport(player_camera.0, local_players.0, window_size));
+        let current = camera.viewport.as_ref().map(|viewport| (viewport.physical_position, viewport.physical_size));
+        if current != split {
+            camera.viewport = split.map(|(physical_position, physical_size)| Viewport { physical_position, physical_size, ..default() });
+        }
+    }
+}
+
+/// Position and size of column `index` of `count` across a window of `window_size`.
+fn split_viewport(index: usize, count: usize, window_size: UVec2) -> (UVec2, UVec2) {
+    let start = window_size.x * index as u32 / count as u32;
+    let end = window_size.x * (index as u32 + 1) / count as u32;
+    return (UVec2::new(start, 0), UVec2::new((end - start).max(1), window_size.y.max(1)));
+}
+
+/// World-space position under a window-space cursor, as seen through `camera`'s viewport.
+pub(crate) fn cursor_to_world(camera: &Camera, camera_transform: &GlobalTransform, cursor_position: Vec2) -> Option<Vec2> {
+    let viewport_min = camera.logical_viewport_rect()?.min;
+    return camera.viewport_to_world_2d(camera_transform, cursor_position - viewport_min);
+}
+
+fn zoom(scale: f32, lines: f32, follow: &CameraFollow) -> f32 {
+    return (scale * (1. - follow.zoom_step).powf(lines)).clamp(follow.min_scale, follow.max_scale);
+}
+
+/// Keeps a view of `half_size` around `center` inside `bounds`, centering on `bounds` along any
+/// axis where the view is larger than the level.
+fn clamp_to_bounds(center: Vec2, half_size: Vec2, bounds: Rect) -> Vec2 {
+    let min = bounds.min + half_size;
+    let max = bounds.max - half_size;
+    let x = if min.x <= max.x { center.x.clamp(min.x, max.x) } else { bounds.center().x };
+    let y = if min.y <= max.y { center.y.clamp(min.y, max.y) } else { bounds.center().y };
+    return Vec2::new(x, y);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Rect, UVec2, Vec2};
+    use crate::camera::{CameraFollow, clamp_to_bounds, split_viewport, zoom};
+
+    #[test]
+    fn test_clamp_inside() {
+        let bounds = Rect::new(-10., -10., 10., 10.);
+
+        assert_eq!(clamp_to_bounds(Vec2::new(1., 2.), Vec2::splat(2.), bounds), Vec2::new(1., 2.));
+    }
+
+    #[test]
+    fn test_clamp_outside() {
+        let bounds = Rect::new(-10., -10., 10., 10.);
+
+        assert_eq!(clamp_to_bounds(Vec2::new(20., -20.), Vec2::splat(2.), bounds), Vec2::new(8., -8.));
+    }
+
+    #[test]
+    fn test_clamp_view_larger_than_bounds() {
+        let bounds = Rect::new(0., 0., 10., 40.);
+
+        assert_eq!(clamp_to_bounds(Vec2::new(20., 30.), Vec2::new(8., 4.), bounds), Vec2::new(5., 30.));
+    }
+
+    #[test]
+    fn test_zoom_limits() {
+        let follow = CameraFollow::default();
+
+        assert!(zoom(1. / 16., 1., &follow) < 1. / 16.);
+        assert!(zoom(1. / 16., -1., &follow) > 1. / 16.);
+        assert_eq!(zoom(1. / 16., 1000., &follow), follow.min_scale);
+        assert_eq!(zoom(1. / 16., -1000., &follow), follow.max_scale);
+    }
+
+    #[test]
+    fn test_split_viewport() {
+        let window_size = UVec2::new(1281, 720);
+
+        assert_eq!(split_viewport(0, 2, window_size), (UVec2::new(0, 0), UVec2::new(640, 720)));
+        assert_eq!(split_viewport(1, 2, window_size), (UVec2::new(640, 0), UVec2::new(641, 720)));
+        assert_eq!(split_viewport(0, 1, window_size), (UVec2::new(0, 0), window_size));
+    }
+}
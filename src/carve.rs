@@ -0,0 +1,135 @@
+use bevy::math::Rect;
+use bevy::prelude::Entity;
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+use crate::polygon_transform_bundle::{PolygonTransformBundle, Precision};
+
+/// Pieces of terrain smaller than this, in global space, are carved away entirely.
+pub(crate) const MIN_TERRAIN_AREA: f32 = 0.01;
+
+/// A mouth cutting into terrain this frame.
+#[derive(Clone, Debug)]
+pub(crate) struct Cut {
+    pub(crate) carver: Entity,
+    pub(crate) mouth: PolygonTransformBundle,
+    /// Global-space bounds of `mouth`.
+    pub(crate) aabb: Rect,
+}
+
+/// What the cuts did to one terrain entity.
+#[derive(Debug)]
+pub(crate) struct Carve {
+    pub(crate) entity: Entity,
+    /// What is left, largest first, or `None` when the cuts missed it.
+    pub(crate) pieces: Option<Vec<PolygonTransformBundle>>,
+    /// Global-space region and area each cut that hit removed, by index into the cuts.
+    pub(crate) removed: Vec<(usize, Rect, f32)>,
+}
+
+/// Applies every cut to `terrain` in order, each to the result of the previous one, so worms
+/// digging the same terrain in the same frame don't overwrite each other's tunnels.
+pub(crate) fn carve(entity: Entity, terrain: PolygonTransformBundle, cuts: &[Cut], precision: &Precision) -> Carve {
+    let terrain_aabb = terrain.polygon.to_global_space(&terrain.transform).aabb();
+    let original = terrain.polygon.clone();
+    let mut pieces = vec![terrain];
+    let mut removed = vec![];
+    for (index, cut) in cuts.iter().enumerate() {
+        if cut.aabb.intersect(terrain_aabb).is_empty() {
+            continue;
+        }
+        let mut removed_area = pieces.iter().map(|piece| piece.area()).sum::<f32>();
+        pieces = pieces.into_iter().flat_map(|piece| piece.sink_with(&cut.mouth, precision)).collect();
+        pieces.retain(|piece| piece.area() > MIN_TERRAIN_AREA);
+        removed_area -= pieces.iter().map(|piece| piece.area()).sum::<f32>();
+        if removed_area > 0. {
+            removed.push((index, cut.aabb.intersect(terrain_aabb), removed_area));
+        }
+    }
+
+    if pieces.len() == 1 && pieces[0].polygon == original {
+        return Carve { entity, pieces: None, removed };
+    }
+    pieces.sort_by(|a, b| b.area().total_cmp(&a.area()));
+    return Carve { entity, pieces: Some(pieces), removed };
+}
+
+/// Carves each terrain entity on its own task in the `ComputeTaskPool`, returning the results in
+/// the order the terrain was given.
+pub(crate) fn carve_parallel(terrain: Vec<(Entity, PolygonTransformBundle)>, cuts: &[Cut], precision: &Precision) -> Vec<Carve> {
+    if terrain.len() <= 1 {
+        return terrain.into_iter().map(|(entity, bundle)| carve(entity, bundle, cuts, precision)).collect();
+    }
+    return ComputeTaskPool::get_or_init(TaskPool::default).scope(|scope| {
+        for (entity, bundle) in terrain {
+            scope.spawn(async move { carve(entity, bundle, cuts, precision) });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Rect, Vec2, Vec3};
+    use bevy::prelude::{Entity, Transform};
+    use crate::carve::{Cut, carve, carve_parallel};
+    use crate::polygon::Polygon;
+    use crate::polygon_transform_bundle::{PolygonTransformBundle, Precision};
+
+    fn chunk(x: f32) -> PolygonTransformBundle {
+        return PolygonTransformBundle::from((
+            Polygon::rectangle(Vec2::ONE),
+            Transform::from_xyz(x, 0., 0.).with_scale(Vec3::splat(8.)),
+        ));
+    }
+
+    fn cut(center: Vec2) -> Cut {
+        let mouth = PolygonTransformBundle::from((Polygon::rectangle(Vec2::splat(2.)), Transform::from_translation(center.extend(0.))));
+        return Cut { carver: Entity::from_raw(0), mouth, aabb: Rect::from_center_size(center, Vec2::splat(2.)) };
+    }
+
+    #[test]
+    fn test_carve_miss() {
+        let actual = carve(Entity::from_raw(1), chunk(0.), &[cut(Vec2::new(32., 0.))], &Precision::default());
+
+        assert!(actual.pieces.is_none());
+        assert!(actual.removed.is_empty());
+    }
+
+    #[test]
+    fn test_carve_aabb_only() {
+        let round_chunk = PolygonTransformBundle::from((
+            Polygon::circle(0.5, 32),
+            Transform::from_xyz(0., 0., 0.).with_scale(Vec3::splat(8.)),
+        ));
+
+        // The corner of the chunk's bounds, outside the circle inscribed in them.
+        let actual = carve(Entity::from_raw(1), round_chunk, &[cut(Vec2::new(4.5, 4.5))], &Precision::default());
+
+        assert!(actual.pieces.is_none());
+        assert!(actual.removed.is_empty());
+    }
+
+    #[test]
+    fn test_carve_hit() {
+        let actual = carve(Entity::from_raw(1), chunk(0.), &[cut(Vec2::new(32., 0.)), cut(Vec2::new(0., 4.))], &Precision::default());
+
+        assert_eq!(actual.pieces.unwrap().len(), 1);
+        assert_eq!(actual.removed.len(), 1);
+        assert_eq!(actual.removed[0].0, 1);
+        assert!((actual.removed[0].2 - 2.).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let terrain: Vec<_> = (0..8).map(|index| (Entity::from_raw(index), chunk(index as f32 * 8.))).collect();
+        let cuts = [cut(Vec2::new(12., 4.)), cut(Vec2::new(36., -4.))];
+
+        let expected: Vec<_> = terrain.iter().cloned()
+            .map(|(entity, bundle)| carve(entity, bundle, &cuts, &Precision::default()))
+            .map(|carve| (carve.entity, carve.pieces, carve.removed))
+            .collect();
+        let actual: Vec<_> = carve_parallel(terrain, &cuts, &Precision::default()).into_iter()
+            .map(|carve| (carve.entity, carve.pieces, carve.removed))
+            .collect();
+
+        assert_eq!(actual, expected);
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+use bevy::math::Vec2;
+use bevy::prelude::{Changed, Commands, Component, Entity, Query, RemovedComponents, Transform};
+use bevy_rapier2d::geometry::Collider;
+use crate::level::TerrainMaterial;
+use crate::polygon::Polygon;
+use crate::spawn_terrain;
+
+/// Value of every corner beyond the grid, which is never solid.
+const OUTSIDE: f32 = 1.;
+
+/// A grid edge: the one from corner `(x, y)` to `(x, y + 1)` if vertical, else to `(x + 1, y)`.
+type Edge = (i64, i64, bool);
+
+/// Outlines of the region where `value` is negative, traced by marching squares over the corners
+/// of a `width` by `height` grid of cells and given in grid units, corner `(0, 0)` at the origin.
+/// Everything beyond the grid counts as empty. Outlines wind clockwise, with each hole bridged into
+/// the outline around it so one `Polygon` describes a solid and all its holes.
+pub(crate) fn contours(width: usize, height: usize, value: impl Fn(usize, usize) -> f32) -> Vec<Polygon> {
+    let value = |x: i64, y: i64| {
+        if x < 0 || y < 0 || x > width as i64 || y > height as i64 {
+            return OUTSIDE;
+        }
+        return value(x as usize, y as usize);
+    };
+    let point = |(x, y, vertical): Edge| {
+        let (end_x, end_y) = if vertical { (x, y + 1) } else { (x + 1, y) };
+        let (start_value, end_value) = (value(x, y), value(end_x, end_y));
+        let along = (start_value / (start_value - end_value)).clamp(0., 1.);
+        return Vec2::new(x as f32, y as f32).lerp(Vec2::new(end_x as f32, end_y as f32), along);
+    };
+
+    // Each cell contributes segments with the solid on their right, which chain clockwise around
+    // solids and counter-clockwise around holes.
+    let mut segments: BTreeMap<Edge, Edge> = BTreeMap::new();
+    for y in -1..=height as i64 {
+        for x in -1..=width as i64 {
+            let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+            let edges = [(x, y, false), (x + 1, y, true), (x, y + 1, false), (x, y, true)];
+            let solid = corners.map(|(x, y)| value(x, y) < 0.);
+
+            // Where the outline crosses the cell's edges, counter-clockwise, and whether it enters
+            // the solid there.
+            let crossings: Vec<(Edge, bool)> = (0..4)
+                .filter(|&index| solid[index] != solid[(index + 1) % 4])
+                .map(|index| (edges[index], solid[(index + 1) % 4]))
+                .collect();
+            // Opposite corners solid: the center decides whether they join across the cell.
+            let joined = crossings.len() == 4 && corners.iter().map(|(x, y)| value(*x, *y)).sum::<f32>() < 0.;
+            for (index, (edge, enters)) in crossings.iter().enumerate() {
+                if !enters {
+                    continue;
+                }
+                let exit = if joined { (index + crossings.len() - 1) % crossings.len() } else { (index + 1) % crossings.len() };
+                segments.insert(*edge, crossings[exit].0);
+            }
+        }
+    }
+
+    let mut outlines = vec![];
+    let mut holes = vec![];
+    while let Some((&first, _)) = segments.first_key_value() {
+        let mut vertices = vec![];
+        let mut edge = first;
+        while let Some(next) = segments.remove(&edge) {
+            vertices.push(point(edge));
+            edge = next;
+        }
+        let outline = Polygon::from(vertices);
+        if outline.signed_area() < 0. {
+            outlines.push(outline);
+        } else {
+            holes.push(outline);
+        }
+    }
+
+    // Bridging rightmost holes first means later bridges can't cross them.
+    holes.sort_by(|a, b| rightmost(b).1.x.total_cmp(&rightmost(a).1.x));
+    for hole in holes {
+        let around = outlines.iter_mut()
+            .filter(|outline| outline.contains(hole.vertices[0]))
+            .min_by(|a, b| a.area().total_cmp(&b.area()));
+        if let Some(outline) = around {
+            bridge(outline, &hole);
+        }
+    }
+    return outlines;
+}
+
+/// Terrain kept as a grid of samples, such as a `DensityChunk` or `BitmapTile`, and outlined into
+/// `Contour`s whenever it changes.
+pub(crate) trait Outlined: Component {
+    /// Outlines of the solid parts, in the entity's local space.
+    fn contours(&self) -> Vec<Polygon>;
+}
+
+/// Outline extracted from an `Outlined` entity. Being a `Polygon` like any other terrain, it is what
+/// gizmos, the nav grid and items see, but it is never carved itself.
+#[derive(Component)]
+pub(crate) struct Contour(pub(crate) Entity);
+
+/// Rebuilds the `Contour`s of every `T` that was spawned or carved, and despawns those of every
+/// `T` that went away.
+pub(crate) fn update_contours<T: Outlined>(
+    mut commands: Commands,
+    source_query: Query<(Entity, &T, &Transform, &TerrainMaterial), Changed<T>>,
+    contour_query: Query<(Entity, &Contour)>,
+    mut removed: RemovedComponents<T>,
+) {
+    let removed: Vec<Entity> = removed.read().collect();
+    for (entity, contour) in contour_query.iter() {
+        if removed.contains(&contour.0) || source_query.contains(contour.0) {
+            commands.entity(entity).despawn();
+        }
+    }
+    for (source_entity, source, transform, material) in source_query.iter() {
+        for outline in source.contours() {
+            let collider = collider(&outline);
+            let entity = spawn_terrain(&mut commands, outline, *transform, *material);
+            commands.entity(entity).insert((Contour(source_entity), collider));
+        }
+    }
+}
+
+/// The outline as a closed polyline, so what's drawn is what worms and debris land on.
+fn collider(outline: &Polygon) -> Collider {
+    let count = outline.vertices.len() as u32;
+    let indices = (0..count).map(|index| [index, (index + 1) % count]).collect();
+    return Collider::polyline(outline.vertices.clone(), Some(indices));
+}
+
+fn rightmost(polygon: &Polygon) -> (usize, Vec2) {
+    return polygon.vertices.iter()
+        .copied()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.x.total_cmp(&b.x))
+        .unwrap();
+}
+
+/// Splices `hole` into `outline` along a slit running right from the hole's rightmost vertex to
+/// the first edge of `outline` it meets.
+fn bridge(outline: &mut Polygon, hole: &Polygon) {
+    let (start, from) = rightmost(hole);
+    let vertices = &outline.vertices;
+    let mut hit: Option<(usize, f32)> = None;
+    for index in 0..vertices.len() {
+        let (a, b) = (vertices[index], vertices[(index + 1) % vertices.len()]);
+        if (a.y > from.y) == (b.y > from.y) {
+            continue;
+        }
+        let x = a.x + (from.y - a.y) * (b.x - a.x) / (b.y - a.y);
+        if x >= from.x && !hit.is_some_and(|(_, hit_x)| hit_x <= x) {
+            hit = Some((index, x));
+        }
+    }
+    let Some((index, x)) = hit else {
+        return;
+    };
+
+    let to = Vec2::new(x, from.y);
+    let mut spliced = vertices[..=index].to_vec();
+    spliced.push(to);
+    spliced.extend(hole.vertices[start..].iter().chain(hole.vertices[..=start].iter()));
+    spliced.push(to);
+    spliced.extend_from_slice(&vertices[index + 1..]);
+    outline.vertices = spliced;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+    use crate::contour::contours;
+
+    #[test]
+    fn test_contours_single_sample() {
+        let actual = contours(2, 2, |x, y| if (x, y) == (1, 1) { -1. } else { 1. });
+
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].vertices.len(), 4);
+        assert!(actual[0].signed_area() < 0.);
+        assert!((actual[0].area() - 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_contours_hole() {
+        let actual = contours(4, 4, |x, y| if (x, y) == (2, 2) { 1. } else { -1. });
+
+        assert_eq!(actual.len(), 1);
+        assert!((actual[0].area() - 24.).abs() < 0.0001);
+        assert!(!actual[0].contains(Vec2::new(2., 2.)));
+        assert!(actual[0].contains(Vec2::new(1., 1.)));
+        assert!(actual[0].contains(Vec2::new(3., 1.)));
+    }
+
+    #[test]
+    fn test_contours_separate() {
+        let actual = contours(4, 0, |x, _| if x % 2 == 0 { -1. } else { 1. });
+
+        assert_eq!(actual.len(), 3);
+    }
+}
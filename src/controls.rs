@@ -0,0 +1,254 @@
+use bevy::math::Vec2;
+use bevy::prelude::{Component, KeyCode, Resource, Transform};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Component, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub(crate) struct Controls {
+    pub(crate) left: bool,
+    pub(crate) right: bool,
+    pub(crate) action: bool,
+    pub(crate) up: bool,
+    pub(crate) down: bool,
+    /// Left mouse button.
+    pub(crate) pointer: bool,
+    /// Cursor position in world space, if it is over the window.
+    pub(crate) cursor: Option<Vec2>,
+}
+
+/// Keys that drive one local player's `Controls`.
+#[derive(Clone, Component, Copy, Debug, PartialEq)]
+pub(crate) struct InputBinding {
+    pub(crate) up: KeyCode,
+    pub(crate) down: KeyCode,
+    pub(crate) left: KeyCode,
+    pub(crate) right: KeyCode,
+    pub(crate) action: KeyCode,
+    /// Whether the mouse sets this player's `pointer` and `cursor`.
+    pub(crate) mouse: bool,
+}
+
+impl InputBinding {
+    pub(crate) const WASD: InputBinding = InputBinding {
+        up: KeyCode::W,
+        down: KeyCode::S,
+        left: KeyCode::A,
+        right: KeyCode::D,
+        action: KeyCode::Space,
+        mouse: true,
+    };
+    pub(crate) const ARROWS: InputBinding = InputBinding {
+        up: KeyCode::Up,
+        down: KeyCode::Down,
+        left: KeyCode::Left,
+        right: KeyCode::Right,
+        action: KeyCode::ShiftRight,
+        mouse: false,
+    };
+    pub(crate) const IJKL: InputBinding = InputBinding {
+        up: KeyCode::I,
+        down: KeyCode::K,
+        left: KeyCode::J,
+        right: KeyCode::L,
+        action: KeyCode::U,
+        mouse: false,
+    };
+    pub(crate) const NUMPAD: InputBinding = InputBinding {
+        up: KeyCode::Numpad8,
+        down: KeyCode::Numpad5,
+        left: KeyCode::Numpad4,
+        right: KeyCode::Numpad6,
+        action: KeyCode::Numpad0,
+        mouse: false,
+    };
+
+    /// Default binding of the local player numbered `index`.
+    pub(crate) fn for_player(index: usize) -> InputBinding {
+        let bindings = [InputBinding::WASD, InputBinding::ARROWS, InputBinding::IJKL, InputBinding::NUMPAD];
+        return bindings[index % bindings.len()];
+    }
+
+    pub(crate) fn apply(&self, controls: &mut Controls, key_code: KeyCode, pressed: bool) {
+        if key_code == self.up {
+            controls.up = pressed;
+        } else if key_code == self.down {
+            controls.down = pressed;
+        } else if key_code == self.left {
+            controls.left = pressed;
+        } else if key_code == self.right {
+            controls.right = pressed;
+        } else if key_code == self.action {
+            controls.action = pressed;
+        }
+    }
+}
+
+/// How `Controls` turn into movement and digging, shared by every player the mouse drives. Cycled
+/// at runtime with Tab. Key names below are for the `WASD` binding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Resource)]
+pub(crate) enum ControlScheme {
+    /// WASD moves, the cursor aims and Space digs.
+    #[default]
+    Keyboard,
+    /// WASD moves, the cursor aims and the left mouse button digs.
+    MouseDig,
+    /// The worm crawls toward the cursor and the left mouse button digs.
+    SteerToCursor,
+    /// A and D turn, W and S crawl forward and backward and Space digs.
+    Tank,
+}
+
+/// Distance from the cursor at which `SteerToCursor` stops crawling, so the worm doesn't jitter
+/// around the pointer.
+const STEER_DEAD_ZONE: f32 = 1.;
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Motion {
+    pub(crate) linvel: Vec2,
+    /// Counter-clockwise rotation to apply this frame, in radians.
+    pub(crate) rotation: f32,
+}
+
+impl ControlScheme {
+    pub(crate) fn next(self) -> ControlScheme {
+        return match self {
+            ControlScheme::Keyboard => ControlScheme::MouseDig,
+            ControlScheme::MouseDig => ControlScheme::SteerToCursor,
+            ControlScheme::SteerToCursor => ControlScheme::Tank,
+            ControlScheme::Tank => ControlScheme::Keyboard,
+        };
+    }
+
+    /// The scheme the player with `binding` plays with. Players without the mouse have no cursor to
+    /// aim or steer at, so they always use `Tank`, aiming by turning. Worms without a binding are
+    /// driven from another machine, which has the mouse.
+    pub(crate) fn for_binding(self, binding: Option<&InputBinding>) -> ControlScheme {
+        if binding.is_some_and(|binding| !binding.mouse) {
+            return ControlScheme::Tank;
+        }
+        return self;
+    }
+
+    pub(crate) fn is_digging(self, controls: &Controls) -> bool {
+        return match self {
+            ControlScheme::Keyboard | ControlScheme::Tank => controls.action,
+            ControlScheme::MouseDig | ControlScheme::SteerToCursor => controls.pointer,
+        };
+    }
+
+    pub(crate) fn motion(self, controls: &Controls, transform: &Transform, speed: f32, turn_speed: f32, delta_seconds: f32) -> Motion {
+        let forward = transform.right().truncate();
+        let position = transform.translation.truncate();
+        let aim = controls.cursor
+            .map(|cursor| forward.angle_between(cursor - position))
+            .filter(|angle| angle.is_finite())
+            .unwrap_or(0.);
+
+        return match self {
+            ControlScheme::Keyboard | ControlScheme::MouseDig => {
+                let left = if controls.left { Vec2::NEG_X } else { Vec2::ZERO };
+                let right = if controls.right { Vec2::X } else { Vec2::ZERO };
+                let up = if controls.up { Vec2::Y } else { Vec2::ZERO };
+                let down = if controls.down { Vec2::NEG_Y } else { Vec2::ZERO };
+                Motion { linvel: speed * (left + right + up + down), rotation: aim }
+            }
+            ControlScheme::SteerToCursor => {
+                let linvel = controls.cursor
+                    .map(|cursor| cursor - position)
+                    .filter(|offset| offset.length() > STEER_DEAD_ZONE)
+                    .map(|offset| speed * offset.normalize())
+                    .unwrap_or(Vec2::ZERO);
+                Motion { linvel, rotation: aim }
+            }
+            ControlScheme::Tank => {
+                let turn = (controls.left as i8 - controls.right as i8) as f32;
+                let crawl = (controls.up as i8 - controls.down as i8) as f32;
+                Motion {
+                    linvel: speed * crawl * forward,
+                    rotation: turn_speed * turn * delta_seconds,
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+    use bevy::math::{Quat, Vec2};
+    use bevy::prelude::Transform;
+    use bevy::prelude::KeyCode;
+    use crate::controls::{ControlScheme, Controls, InputBinding};
+
+    #[test]
+    fn test_keyboard_aims_at_cursor() {
+        let controls = Controls { right: true, cursor: Some(Vec2::new(0., 4.)), ..Controls::default() };
+
+        let actual = ControlScheme::Keyboard.motion(&controls, &Transform::default(), 16., 4., 0.5);
+
+        assert_eq!(actual.linvel, Vec2::new(16., 0.));
+        assert!((actual.rotation - FRAC_PI_2).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_steer_to_cursor() {
+        let controls = Controls { cursor: Some(Vec2::new(0., 4.)), ..Controls::default() };
+
+        let actual = ControlScheme::SteerToCursor.motion(&controls, &Transform::default(), 16., 4., 0.5);
+
+        assert_eq!(actual.linvel, Vec2::new(0., 16.));
+        assert!((actual.rotation - FRAC_PI_2).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_steer_to_cursor_dead_zone() {
+        let controls = Controls { cursor: Some(Vec2::new(0.5, 0.)), ..Controls::default() };
+
+        let actual = ControlScheme::SteerToCursor.motion(&controls, &Transform::default(), 16., 4., 0.5);
+
+        assert_eq!(actual.linvel, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_tank_crawls_forward_and_turns() {
+        let controls = Controls { up: true, left: true, cursor: Some(Vec2::new(0., -4.)), ..Controls::default() };
+        let transform = Transform::from_rotation(Quat::from_rotation_z(FRAC_PI_2));
+
+        let actual = ControlScheme::Tank.motion(&controls, &transform, 16., 4., 0.5);
+
+        assert!(actual.linvel.abs_diff_eq(Vec2::new(0., 16.), 0.0001));
+        assert_eq!(actual.rotation, 2.);
+    }
+
+    #[test]
+    fn test_is_digging() {
+        let space = Controls { action: true, ..Controls::default() };
+        let mouse = Controls { pointer: true, ..Controls::default() };
+
+        assert!(ControlScheme::Keyboard.is_digging(&space));
+        assert!(!ControlScheme::Keyboard.is_digging(&mouse));
+        assert!(ControlScheme::MouseDig.is_digging(&mouse));
+        assert!(!ControlScheme::MouseDig.is_digging(&space));
+        assert!(ControlScheme::Tank.is_digging(&space));
+    }
+
+    #[test]
+    fn test_keyboard_only_players_use_tank() {
+        assert_eq!(ControlScheme::MouseDig.for_binding(Some(&InputBinding::WASD)), ControlScheme::MouseDig);
+        assert_eq!(ControlScheme::MouseDig.for_binding(Some(&InputBinding::ARROWS)), ControlScheme::Tank);
+        assert_eq!(ControlScheme::SteerToCursor.for_binding(None), ControlScheme::SteerToCursor);
+    }
+
+    #[test]
+    fn test_bindings_are_independent() {
+        let mut wasd = Controls::default();
+        let mut arrows = Controls::default();
+
+        for (key_code, pressed) in [(KeyCode::W, true), (KeyCode::Left, true), (KeyCode::ShiftRight, true), (KeyCode::W, false)] {
+            InputBinding::for_player(0).apply(&mut wasd, key_code, pressed);
+            InputBinding::for_player(1).apply(&mut arrows, key_code, pressed);
+        }
+
+        assert!(!wasd.up && !wasd.left && !wasd.action);
+        assert!(arrows.left && arrows.action && !arrows.up);
+    }
+}
@@ -0,0 +1,139 @@
+use bevy::math::Rect;
+use bevy::prelude::{Added, Commands, Component, Entity, EventReader, Query, Res, Transform, TransformBundle, With};
+use bevy_rapier2d::dynamics::{RigidBody, Sleeping};
+use bevy_rapier2d::geometry::ColliderMassProperties;
+use bevy_rapier2d::prelude::Velocity;
+use crate::camera::LevelBounds;
+use crate::polygon::Polygon;
+use crate::polygon_transform_bundle::PolygonTransformBundle;
+use crate::TerrainCarved;
+
+/// Mass per unit of global-space area.
+const DEBRIS_DENSITY: f32 = 1.;
+/// How close, in world units, terrain has to come to other terrain or the edge of the level to
+/// rest on it.
+pub(crate) const SUPPORT_DISTANCE: f32 = 0.05;
+
+/// Terrain cut loose from the piece it was anchored to, now falling under gravity.
+#[derive(Component)]
+pub(crate) struct Debris;
+
+pub(crate) fn spawn_debris(commands: &mut Commands, bundle: PolygonTransformBundle) -> Entity {
+    // `Sleeping` is kept up to date only on bodies spawned with it.
+    return commands.spawn(RigidBody::Dynamic)
+        .insert(Sleeping::default())
+        .insert(TransformBundle::from_transform(bundle.transform))
+        .insert(Velocity::default())
+        .insert(bundle.polygon)
+        .insert(Debris)
+        .id();
+}
+
+/// Whether `piece`, in global space, is held up by the edge of the level or by touching any of
+/// `others`, also in global space.
+pub(crate) fn is_supported(piece: &Polygon, bounds: Rect, others: &[Polygon]) -> bool {
+    let aabb = piece.aabb();
+    let inner = Rect::from_center_size(bounds.center(), bounds.size() - 2. * SUPPORT_DISTANCE);
+    if !inner.contains(aabb.min) || !inner.contains(aabb.max) {
+        return true;
+    }
+    return others.iter().any(|other| touches(piece, other) || touches(other, piece));
+}
+
+/// Whether any vertex of `a` lies inside or within `SUPPORT_DISTANCE` of `b`.
+fn touches(a: &Polygon, b: &Polygon) -> bool {
+    return a.vertices.iter().any(|vertex| b.contains(*vertex) || b.distance_to_outline(*vertex) <= SUPPORT_DISTANCE);
+}
+
+/// Despawns debris that has fallen out of the level, which nothing will ever catch.
+pub(crate) fn update_debris_bounds(
+    mut commands: Commands,
+    bounds: Res<LevelBounds>,
+    debris_query: Query<(Entity, &Polygon, &Transform), With<Debris>>,
+) {
+    for (entity, polygon, transform) in debris_query.iter() {
+        if bounds.0.intersect(polygon.to_global_space(transform).aabb()).is_empty() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Rebuilds the collider and mass of any debris that was spawned or carved.
+pub(crate) fn update_debris_collider(
+    mut commands: Commands,
+    mut carved_events: EventReader<TerrainCarved>,
+    added_query: Query<Entity, Added<Debris>>,
+    debris_query: Query<(&Polygon, &Transform), With<Debris>>,
+) {
+    let mut entities: Vec<Entity> = added_query.iter()
+        .chain(carved_events.read().map(|carved_event| carved_event.entity))
+        .collect();
+    entities.sort();
+    entities.dedup();
+
+    for entity in entities {
+        let Ok((polygon, transform)) = debris_query.get(entity) else {
+            continue;
+        };
+        let area = PolygonTransformBundle::from((polygon.clone(), *transform)).area();
+
+        commands.entity(entity)
+            .insert(polygon.collider())
+            .insert(ColliderMassProperties::Mass(DEBRIS_DENSITY * area));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::app::{App, Startup, Update};
+    use bevy::asset::{AssetApp, AssetPlugin};
+    use bevy::math::{Rect, Vec2};
+    use bevy::prelude::{Commands, Mesh, MinimalPlugins, Transform, TransformPlugin, With};
+    use bevy_rapier2d::plugin::{NoUserData, RapierConfiguration, RapierPhysicsPlugin, TimestepMode};
+    use crate::camera::LevelBounds;
+    use crate::debris::{Debris, is_supported, spawn_debris, update_debris_bounds, update_debris_collider};
+    use crate::level::TerrainMaterial;
+    use crate::polygon::Polygon;
+    use crate::polygon_transform_bundle::PolygonTransformBundle;
+    use crate::{TerrainCarved, spawn_terrain, update_terrain_collider};
+
+    fn square(center: Vec2) -> Polygon {
+        return Polygon::from(Rect::from_center_size(center, Vec2::splat(2.)));
+    }
+
+    #[test]
+    fn test_is_supported() {
+        let bounds = Rect::new(-16., -16., 16., 16.);
+        let floor = [Polygon::from(Rect::new(-8., -4., 8., -2.))];
+
+        assert!(is_supported(&square(Vec2::new(0., -1.)), bounds, &floor));
+        assert!(is_supported(&square(Vec2::new(0., -15.)), bounds, &[]));
+        assert!(!is_supported(&square(Vec2::new(0., 4.)), bounds, &floor));
+    }
+
+    #[test]
+    fn test_rests_on_terrain() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TransformPlugin, AssetPlugin::default()))
+            .init_asset::<Mesh>()
+            .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.))
+            .insert_resource(RapierConfiguration {
+                timestep_mode: TimestepMode::Fixed { dt: 1. / 60., substeps: 1 },
+                ..RapierConfiguration::default()
+            })
+            .insert_resource(LevelBounds(Rect::new(-32., -32., 32., 32.)))
+            .add_event::<TerrainCarved>()
+            .add_systems(Startup, |mut commands: Commands| {
+                spawn_terrain(&mut commands, Polygon::rectangle(Vec2::new(32., 4.)), Transform::from_xyz(0., -10., 0.), TerrainMaterial::Dirt);
+                spawn_debris(&mut commands, PolygonTransformBundle::from((Polygon::rectangle(Vec2::splat(2.)), Transform::IDENTITY)));
+            })
+            .add_systems(Update, (update_terrain_collider, update_debris_collider, update_debris_bounds));
+
+        for _ in 0..600 {
+            app.update();
+        }
+
+        let transform = app.world.query_filtered::<&Transform, With<Debris>>().single(&app.world);
+        assert!((transform.translation.y - -7.).abs() < 0.1, "debris at {}", transform.translation.y);
+    }
+}
@@ -0,0 +1,237 @@
+use bevy::math::{Rect, Vec2, Vec3};
+use bevy::prelude::{Commands, Component, DetectChangesMut, Entity, EventWriter, Query, Transform, TransformBundle};
+use serde::{Deserialize, Serialize};
+use crate::carve::Cut;
+use crate::contour::{Outlined, contours};
+use crate::level::TerrainMaterial;
+use crate::polygon::Polygon;
+use crate::TerrainCarved;
+
+/// Side of a density chunk, in world units.
+const CHUNK_SIZE: f32 = 16.;
+
+/// Samples a chunk keeps beyond each of its sides, which overlap its neighbours' and are carved by
+/// the same cuts, so outlines run on across the seam instead of closing off short of it.
+const APRON: usize = 1;
+
+/// Terrain stored as a signed distance field sampled over a square chunk, negative inside solid
+/// ground. The samples span the chunk's local unit square centered on its origin, the square
+/// `Polygon::rectangle(Vec2::ONE)` covers, plus an `APRON` around it, and measure distance in local
+/// units.
+#[derive(Clone, Component, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct DensityChunk {
+    /// Cells per side, without the apron; there is one more sample than this per side.
+    resolution: usize,
+    /// Samples row by row, apron included.
+    samples: Vec<f32>,
+}
+
+impl DensityChunk {
+    /// Samples `polygon`, given in the chunk's local space.
+    pub(crate) fn from_polygon(polygon: &Polygon, resolution: usize) -> Self {
+        let side = resolution + 1 + 2 * APRON;
+        let mut chunk = DensityChunk { resolution, samples: vec![0.; side * side] };
+        for y in 0..side {
+            for x in 0..side {
+                chunk.samples[y * side + x] = signed_distance(polygon, chunk.position(x, y));
+            }
+        }
+        return chunk;
+    }
+
+    /// Samples per side, apron included.
+    fn side(&self) -> usize {
+        return self.resolution + 1 + 2 * APRON;
+    }
+
+    fn position(&self, x: usize, y: usize) -> Vec2 {
+        return (Vec2::new(x as f32, y as f32) - APRON as f32) / self.resolution as f32 - 0.5;
+    }
+
+    fn sample(&self, x: usize, y: usize) -> f32 {
+        return self.samples[y * self.side() + x];
+    }
+
+    /// Whether nothing within the chunk itself is solid, whatever its apron holds.
+    pub(crate) fn is_empty(&self) -> bool {
+        let interior = APRON..=APRON + self.resolution;
+        return interior.clone().all(|y| interior.clone().all(|x| self.sample(x, y) >= 0.));
+    }
+
+    /// Subtracts `cutter`, given in the chunk's local space, by taking the larger of each sample,
+    /// apron included, and the cutter's negated distance. Returns the local-space area that stopped
+    /// being solid, counted a sample's cell at a time.
+    pub(crate) fn carve(&mut self, cutter: &Polygon) -> f32 {
+        let cell = 1. / self.resolution as f32;
+        let aabb = cutter.aabb();
+        let last = (self.side() - 1) as f32;
+        let min = ((aabb.min + 0.5) / cell + APRON as f32).floor().clamp(Vec2::ZERO, Vec2::splat(last)).as_uvec2();
+        let max = ((aabb.max + 0.5) / cell + APRON as f32).ceil().clamp(Vec2::ZERO, Vec2::splat(last)).as_uvec2();
+        // Samples on the chunk's far sides belong to the next chunk's count, so a cut across a
+        // seam isn't counted twice.
+        let counted = APRON..APRON + self.resolution;
+
+        let mut removed_cells = 0;
+        for y in min.y as usize..=max.y as usize {
+            for x in min.x as usize..=max.x as usize {
+                let index = y * self.side() + x;
+                let carved = self.samples[index].max(-signed_distance(cutter, self.position(x, y)));
+                if self.samples[index] < 0. && carved >= 0. && counted.contains(&x) && counted.contains(&y) {
+                    removed_cells += 1;
+                }
+                self.samples[index] = carved;
+            }
+        }
+        return removed_cells as f32 * cell * cell;
+    }
+}
+
+impl Outlined for DensityChunk {
+    fn contours(&self) -> Vec<Polygon> {
+        // Traced with the apron, then cut back to the chunk, like a `BitmapTile`'s.
+        let cells = self.side() - 1;
+        let chunk = Rect::from_center_size(Vec2::ZERO, Vec2::ONE);
+        return contours(cells, cells, |x, y| self.sample(x, y)).into_iter()
+            .map(|outline| Polygon::from(outline.vertices.iter()
+                .map(|vertex| (*vertex - APRON as f32) / self.resolution as f32 - 0.5)
+                .collect::<Vec<Vec2>>()))
+            .map(|outline| outline.clip(chunk))
+            .filter(|outline| outline.vertices.len() >= 3 && outline.area() > 0.)
+            .collect();
+    }
+}
+
+/// Distance from `point` to the outline of `polygon`, negated inside it.
+fn signed_distance(polygon: &Polygon, point: Vec2) -> f32 {
+    let vertices = &polygon.vertices;
+    let mut distance = f32::INFINITY;
+    for index in 0..vertices.len() {
+        let (a, b) = (vertices[index], vertices[(index + 1) % vertices.len()]);
+        let along = ((point - a).dot(b - a) / (b - a).length_squared()).clamp(0., 1.);
+        distance = distance.min(point.distance(a + along * (b - a)));
+    }
+    return if polygon.contains(point) { -distance } else { distance };
+}
+
+fn chunk_aabb(transform: &Transform) -> Rect {
+    return Rect::from_center_size(transform.translation.truncate(), transform.scale.truncate());
+}
+
+/// Spawns density chunks covering `polygon`, given in global space, skipping those it misses.
+pub(crate) fn spawn_density_terrain(commands: &mut Commands, polygon: &Polygon, material: TerrainMaterial, resolution: usize) {
+    let aabb = polygon.aabb();
+    let min = (aabb.min / CHUNK_SIZE).floor().as_ivec2();
+    let max = (aabb.max / CHUNK_SIZE).ceil().as_ivec2();
+    for y in min.y..max.y {
+        for x in min.x..max.x {
+            let center = (Vec2::new(x as f32, y as f32) + 0.5) * CHUNK_SIZE;
+            let transform = Transform::from_translation(center.extend(0.)).with_scale(Vec3::splat(CHUNK_SIZE));
+            let chunk = DensityChunk::from_polygon(&polygon.to_local_space(transform), resolution);
+            if chunk.is_empty() {
+                continue;
+            }
+            spawn_density_chunk(commands, chunk, transform, material);
+        }
+    }
+}
+
+pub(crate) fn spawn_density_chunk(commands: &mut Commands, chunk: DensityChunk, transform: Transform, material: TerrainMaterial) -> Entity {
+    return commands.spawn(chunk)
+        .insert(TransformBundle::from_transform(transform))
+        .insert(material)
+        .id();
+}
+
+/// Applies this frame's cuts to every density chunk they reach.
+pub(crate) fn carve_density(
+    cuts: &[Cut],
+    chunk_query: &mut Query<(Entity, &mut DensityChunk, &Transform)>,
+    carved_events: &mut EventWriter<TerrainCarved>,
+) {
+    for (entity, mut chunk, transform) in chunk_query.iter_mut() {
+        let aabb = chunk_aabb(transform);
+        for cut in cuts.iter() {
+            let region = cut.aabb.intersect(aabb);
+            if region.is_empty() {
+                continue;
+            }
+            let cutter = cut.mouth.polygon.to_global_space(&cut.mouth.transform).to_local_space(*transform);
+            // Bypass change detection until something is dug out, so the contours aren't rebuilt
+            // for a mouth moving through open space.
+            let removed_area = chunk.bypass_change_detection().carve(&cutter);
+            if removed_area > 0. {
+                chunk.set_changed();
+                carved_events.send(TerrainCarved {
+                    entity,
+                    region,
+                    removed_area: removed_area * transform.scale.x * transform.scale.y,
+                    carver: cut.carver,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+    use bevy::prelude::Transform;
+    use crate::contour::Outlined;
+    use crate::density::DensityChunk;
+    use crate::polygon::Polygon;
+
+    fn area(chunk: &DensityChunk) -> f32 {
+        return chunk.contours().iter().map(|outline| outline.area()).sum();
+    }
+
+    #[test]
+    fn test_from_polygon() {
+        let chunk = DensityChunk::from_polygon(&Polygon::rectangle(Vec2::splat(0.5)), 32);
+
+        assert_eq!(chunk.contours().len(), 1);
+        assert!((area(&chunk) - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_carve() {
+        let mut chunk = DensityChunk::from_polygon(&Polygon::rectangle(Vec2::ONE), 32);
+        let before = area(&chunk);
+
+        let removed_area = chunk.carve(&Polygon::circle(0.25, 32));
+
+        assert!((removed_area - 0.196).abs() < 0.02);
+        assert!((before - area(&chunk) - 0.196).abs() < 0.02);
+        assert_eq!(chunk.contours().len(), 1);
+        assert_eq!(chunk.carve(&Polygon::circle(0.25, 32)), 0.);
+    }
+
+    #[test]
+    fn test_carve_split() {
+        let mut chunk = DensityChunk::from_polygon(&Polygon::rectangle(Vec2::ONE), 32);
+
+        chunk.carve(&Polygon::rectangle(Vec2::new(0.25, 2.)));
+
+        assert_eq!(chunk.contours().len(), 2);
+    }
+
+    #[test]
+    fn test_contours_reach_seam() {
+        let chunk = DensityChunk::from_polygon(&Polygon::rectangle(Vec2::splat(4.)), 8);
+
+        assert_eq!(chunk.contours().len(), 1);
+        assert!((area(&chunk) - 1.).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_carve_across_seam() {
+        let mut left = DensityChunk::from_polygon(&Polygon::rectangle(Vec2::splat(4.)), 32);
+        let mut right = left.clone();
+        let circle = Polygon::circle(0.25, 32);
+
+        let removed_area = left.carve(&circle.to_global_space(&Transform::from_xyz(0.5, 0., 0.)))
+            + right.carve(&circle.to_global_space(&Transform::from_xyz(-0.5, 0., 0.)));
+
+        assert!((removed_area - 0.196).abs() < 0.02);
+        assert!((2. - area(&left) - area(&right) - 0.196).abs() < 0.02);
+    }
+}
@@ -0,0 +1,234 @@
+use bevy::input::ButtonState;
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::mouse::{MouseButton, MouseButtonInput};
+use bevy::log::{info, warn};
+use bevy::math::Vec2;
+use bevy::prelude::{Camera, Commands, Entity, EventReader, GlobalTransform, KeyCode, Query, Res, ResMut, Resource, Transform, With, Without};
+use bevy::window::{PrimaryWindow, Window};
+use crate::camera::cursor_to_world;
+use crate::contour::Contour;
+use crate::debris::Debris;
+use crate::level::{LEVEL_PATH, Level, LevelTerrain, TerrainBackend, TerrainMaterial};
+use crate::polygon::Polygon;
+use crate::{CurrentLevel, spawn_terrain};
+
+/// Distance, in world units, within which a click grabs a vertex or edge.
+pub(crate) const PICK_RADIUS: f32 = 1.;
+const MIN_VERTICES: usize = 3;
+/// Side of the square a new polygon starts as.
+const NEW_POLYGON_SIZE: f32 = 8.;
+
+/// In-game level editor, toggled with F1.
+///
+/// Click a vertex to select and drag it, or an edge to insert a vertex there. Delete or Backspace
+/// removes the selected vertex, N adds a polygon at the cursor, M cycles the selected polygon's
+/// material and F2 writes the terrain to the level file.
+#[derive(Default, Resource)]
+pub(crate) struct Editor {
+    pub(crate) enabled: bool,
+    pub(crate) selected: Option<Entity>,
+    pub(crate) vertex: Option<usize>,
+    dragging: bool,
+}
+
+pub(crate) fn is_editing(editor: Res<Editor>) -> bool {
+    return editor.enabled;
+}
+
+/// Index of the vertex nearest to `point`, if any is within `radius`.
+fn pick_vertex(vertices: &[Vec2], point: Vec2, radius: f32) -> Option<usize> {
+    return vertices.iter()
+        .enumerate()
+        .map(|(index, vertex)| (index, vertex.distance(point)))
+        .filter(|(_, distance)| *distance <= radius)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index);
+}
+
+/// Nearest edge within `radius` of `point`, as the index a vertex inserted on it takes and the
+/// point on the edge closest to `point`.
+fn pick_edge(vertices: &[Vec2], point: Vec2, radius: f32) -> Option<(usize, Vec2)> {
+    let mut nearest = None;
+    let mut nearest_distance = radius;
+    for index in 0..vertices.len() {
+        let start = vertices[index];
+        let edge = vertices[(index + 1) % vertices.len()] - start;
+        let t = ((point - start).dot(edge) / edge.length_squared()).clamp(0., 1.);
+        let closest = start + t * edge;
+        if closest.distance(point) <= nearest_distance {
+            nearest_distance = closest.distance(point);
+            nearest = Some((index + 1, closest));
+        }
+    }
+    return nearest;
+}
+
+fn to_local_point(point: Vec2, transform: &Transform) -> Vec2 {
+    return Polygon::from(vec![point]).to_local_space(*transform).vertices[0];
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn update_editor(
+    mut commands: Commands,
+    mut editor: ResMut<Editor>,
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut mouse_button_events: EventReader<MouseButtonInput>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    current_level: Option<Res<CurrentLevel>>,
+    mut terrain_query: Query<(Entity, &mut Polygon, &Transform, Option<&TerrainMaterial>), (Without<Debris>, Without<Contour>)>,
+) {
+    // The cursor is in whichever player's viewport it is over.
+    let cursor = window_query.single().cursor_position().and_then(|cursor_position| camera_query.iter()
+        .find(|(camera, _)| camera.logical_viewport_rect().is_some_and(|rect| rect.contains(cursor_position)))
+        .and_then(|(camera, camera_transform)| cursor_to_world(camera, camera_transform, cursor_position)));
+
+    for keyboard_event in keyboard_events.read() {
+        if keyboard_event.state != ButtonState::Pressed {
+            continue;
+        }
+        if keyboard_event.key_code == Some(KeyCode::F1) {
+            *editor = Editor { enabled: !editor.enabled, ..Editor::default() };
+            continue;
+        }
+        if !editor.enabled {
+            continue;
+        }
+        match keyboard_event.key_code {
+            Some(KeyCode::Delete | KeyCode::Back) => {
+                let (Some(entity), Some(vertex)) = (editor.selected, editor.vertex.take()) else {
+                    continue;
+                };
+                let Ok((_, mut polygon, _, _)) = terrain_query.get_mut(entity) else {
+                    continue;
+                };
+                if polygon.vertices.len() > MIN_VERTICES {
+                    polygon.vertices.remove(vertex);
+                } else {
+                    commands.entity(entity).despawn();
+                    editor.selected = None;
+                }
+            }
+            Some(KeyCode::N) => {
+                if let Some(cursor) = cursor {
+                    spawn_terrain(
+                        &mut commands,
+                        Polygon::rectangle(Vec2::splat(NEW_POLYGON_SIZE)),
+                        Transform::from_translation(cursor.extend(0.)),
+                        TerrainMaterial::default(),
+                    );
+                }
+            }
+            Some(KeyCode::M) => {
+                let Some((entity, _, _, material)) = editor.selected.and_then(|entity| terrain_query.get(entity).ok()) else {
+                    continue;
+                };
+                commands.entity(entity).insert(material.copied().unwrap_or_default().next());
+            }
+            Some(KeyCode::F2) => {
+                let current = current_level.as_ref().map(|current_level| current_level.level.clone()).unwrap_or_default();
+                if current.backend != TerrainBackend::Polygon {
+                    warn!("only levels with polygon terrain can be saved from the editor");
+                    continue;
+                }
+                // Everything but the terrain is kept as it was. Masks were traced into the terrain
+                // being written, so they go.
+                let level = Level {
+                    terrain: terrain_query.iter()
+                        .map(|(_, polygon, transform, material)| LevelTerrain {
+                            polygon: polygon.clone(),
+                            transform: *transform,
+                            material: material.copied().unwrap_or_default(),
+                        })
+                        .collect(),
+                    masks: vec![],
+                    ..current
+                };
+                match level.write(LEVEL_PATH) {
+                    Ok(()) => info!("saved level to {LEVEL_PATH}"),
+                    Err(error) => warn!("{error}"),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !editor.enabled {
+        mouse_button_events.clear();
+        return;
+    }
+
+    for mouse_button_event in mouse_button_events.read() {
+        match (mouse_button_event.button, mouse_button_event.state) {
+            (MouseButton::Left, ButtonState::Pressed) => {
+                let Some(cursor) = cursor else {
+                    continue;
+                };
+                *editor = Editor { enabled: true, ..Editor::default() };
+                for (entity, mut polygon, transform, _) in terrain_query.iter_mut() {
+                    let global_vertices = polygon.to_global_space(transform).vertices;
+                    if let Some(vertex) = pick_vertex(&global_vertices, cursor, PICK_RADIUS) {
+                        *editor = Editor { enabled: true, selected: Some(entity), vertex: Some(vertex), dragging: true };
+                        break;
+                    }
+                    if let Some((vertex, position)) = pick_edge(&global_vertices, cursor, PICK_RADIUS) {
+                        polygon.vertices.insert(vertex, to_local_point(position, transform));
+                        *editor = Editor { enabled: true, selected: Some(entity), vertex: Some(vertex), dragging: true };
+                        break;
+                    }
+                    if polygon.to_global_space(transform).contains(cursor) {
+                        editor.selected = Some(entity);
+                    }
+                }
+            }
+            (MouseButton::Left, ButtonState::Released) => { editor.dragging = false }
+            _ => {}
+        }
+    }
+
+    if let (true, Some(cursor), Some(entity), Some(vertex)) = (editor.dragging, cursor, editor.selected, editor.vertex) {
+        if let Ok((_, mut polygon, transform, _)) = terrain_query.get_mut(entity) {
+            let position = to_local_point(cursor, transform);
+            if polygon.vertices[vertex] != position {
+                polygon.vertices[vertex] = position;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Vec2, Vec3};
+    use bevy::prelude::Transform;
+    use crate::editor::{pick_edge, pick_vertex, to_local_point};
+
+    fn square() -> Vec<Vec2> {
+        return vec![
+            Vec2::new(0., 4.),
+            Vec2::new(4., 4.),
+            Vec2::new(4., 0.),
+            Vec2::new(0., 0.),
+        ];
+    }
+
+    #[test]
+    fn test_pick_vertex() {
+        assert_eq!(pick_vertex(&square(), Vec2::new(3.5, 0.25), 1.), Some(2));
+        assert_eq!(pick_vertex(&square(), Vec2::new(2., 2.), 1.), None);
+    }
+
+    #[test]
+    fn test_pick_edge() {
+        assert_eq!(pick_edge(&square(), Vec2::new(2., 4.5), 1.), Some((1, Vec2::new(2., 4.))));
+        assert_eq!(pick_edge(&square(), Vec2::new(-0.5, 2.), 1.), Some((4, Vec2::new(0., 2.))));
+        assert_eq!(pick_edge(&square(), Vec2::new(2., 2.), 1.), None);
+    }
+
+    #[test]
+    fn test_to_local_point() {
+        let transform = Transform::from_xyz(32., -32., 0.)
+            .with_scale(Vec3::splat(64.));
+
+        assert_eq!(to_local_point(Vec2::new(64., 0.), &transform), Vec2::new(0.5, 0.5));
+    }
+}
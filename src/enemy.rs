@@ -0,0 +1,76 @@
+use bevy::math::Vec2;
+use bevy::prelude::{Commands, Component, DetectChanges, Query, Res, Time, Timer, TimerMode, Transform, TransformBundle, With, Without};
+use bevy_rapier2d::dynamics::RigidBody;
+use bevy_rapier2d::geometry::Collider;
+use bevy_rapier2d::prelude::{GravityScale, LockedAxes, Velocity};
+use serde::{Deserialize, Serialize};
+use crate::navigation::NavGrid;
+use crate::Player;
+
+/// Waypoints closer than this are considered reached.
+const WAYPOINT_RADIUS: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) enum EnemyBehavior {
+    /// Path toward the nearest worm.
+    Chase,
+    /// Run to the reachable cell farthest from the nearest worm within this many steps.
+    Flee(usize),
+}
+
+#[derive(Component)]
+pub(crate) struct Enemy {
+    pub(crate) behavior: EnemyBehavior,
+    pub(crate) speed: f32,
+    path: Vec<Vec2>,
+    repath: Timer,
+}
+
+impl Enemy {
+    pub(crate) fn new(behavior: EnemyBehavior, speed: f32) -> Self {
+        let mut repath = Timer::from_seconds(0.5, TimerMode::Repeating);
+        repath.set_elapsed(repath.duration());
+        Enemy { behavior, speed, path: vec![], repath }
+    }
+}
+
+pub(crate) fn spawn_enemy(commands: &mut Commands, enemy: Enemy, transform: Transform) {
+    commands.spawn(RigidBody::Dynamic)
+        .insert(TransformBundle::from_transform(transform))
+        .insert(GravityScale(0.))
+        .insert(LockedAxes::ROTATION_LOCKED)
+        .insert(Velocity::default())
+        .insert(Collider::ball(1.))
+        .insert(enemy);
+}
+
+pub(crate) fn update_enemies(
+    time: Res<Time>,
+    nav_grid: Res<NavGrid>,
+    player_query: Query<&Transform, With<Player>>,
+    mut enemy_query: Query<(&mut Enemy, &mut Velocity, &Transform), Without<Player>>,
+) {
+    for (mut enemy, mut velocity, transform) in enemy_query.iter_mut() {
+        let position = transform.translation.truncate();
+        let Some(player_position) = player_query.iter()
+            .map(|player_transform| player_transform.translation.truncate())
+            .min_by(|a, b| a.distance_squared(position).total_cmp(&b.distance_squared(position))) else {
+            continue;
+        };
+
+        if enemy.repath.tick(time.delta()).just_finished() || nav_grid.is_changed() {
+            enemy.path = match enemy.behavior {
+                EnemyBehavior::Chase => nav_grid.path(position, player_position),
+                EnemyBehavior::Flee(steps) => nav_grid.flee(position, player_position, steps),
+            }.unwrap_or_default();
+        }
+
+        while enemy.path.first().is_some_and(|waypoint| waypoint.distance(position) < WAYPOINT_RADIUS) {
+            enemy.path.remove(0);
+        }
+
+        velocity.linvel = enemy.path.first()
+            .map(|waypoint| enemy.speed * (*waypoint - position).normalize_or_zero())
+            .unwrap_or(Vec2::ZERO);
+    }
+}
@@ -0,0 +1,245 @@
+use bevy::math::{Rect, Vec2};
+use bevy::prelude::{Color, DetectChanges, Gizmos, Query, Res, ResMut, Resource, Time, Transform, With};
+use bevy_rapier2d::prelude::Velocity;
+use crate::navigation::NavGrid;
+use crate::{Health, Player};
+
+/// Water a cell holds at rest.
+const MAX_WATER: f32 = 1.;
+/// Extra water a cell may hold per cell of water above it, which lets pressure push water up.
+const MAX_COMPRESSION: f32 = 0.02;
+/// Smaller flows are dropped to let the simulation settle.
+const MIN_FLOW: f32 = 0.005;
+/// Cells fuller than this count as submerging the worm.
+const SUBMERGED: f32 = 0.5;
+const WATER_DRAG: f32 = 0.5;
+/// Health lost per second while submerged.
+const WATER_DAMAGE: f32 = 10.;
+
+/// Cellular water simulation over the cells of the `NavGrid`. Terrain bounds it: water only lives
+/// in and flows between cells the nav grid has open, so carving a tunnel next to water lets it
+/// flood in.
+#[derive(Default, Resource)]
+pub(crate) struct FluidGrid {
+    width: usize,
+    height: usize,
+    water: Vec<f32>,
+    /// Regions to fill with water once the nav grid knows where the terrain is.
+    pending: Vec<Rect>,
+}
+
+impl FluidGrid {
+    /// Drains all the water, to fill `water_bodies` instead once the nav grid has been rebuilt
+    /// around the terrain laid out with them.
+    pub(crate) fn reset(&mut self, water_bodies: Vec<Rect>) {
+        self.water.fill(0.);
+        self.pending = water_bodies;
+    }
+
+    fn resize(&mut self, (width, height): (usize, usize)) {
+        self.width = width;
+        self.height = height;
+        self.water = vec![0.; width * height];
+    }
+
+    pub(crate) fn water(&self, (x, y): (usize, usize)) -> f32 {
+        return self.water[y * self.width + x];
+    }
+
+    /// Fills every open cell whose center is inside `region`.
+    fn fill(&mut self, nav_grid: &NavGrid, region: Rect) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if nav_grid.is_open((x, y)) && region.contains(nav_grid.center((x, y))) {
+                    self.water[y * self.width + x] = MAX_WATER;
+                }
+            }
+        }
+    }
+
+    /// Water that should sit in the lower of two stacked cells holding `total` between them.
+    fn stable_lower(total: f32) -> f32 {
+        if total <= MAX_WATER {
+            return MAX_WATER;
+        } else if total < 2. * MAX_WATER + MAX_COMPRESSION {
+            return (MAX_WATER * MAX_WATER + total * MAX_COMPRESSION) / (MAX_WATER + MAX_COMPRESSION);
+        }
+        return (total + MAX_COMPRESSION) / 2.;
+    }
+
+    /// Advances the simulation one tick: each cell drains down first, then spreads to its sides,
+    /// then pushes any compressed excess up.
+    pub(crate) fn step(&mut self, is_open: impl Fn((usize, usize)) -> bool) {
+        let mut next = self.water.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = y * self.width + x;
+                if !is_open((x, y)) {
+                    next[index] = 0.;
+                    continue;
+                }
+
+                let mut remaining = self.water[index];
+                if remaining <= 0. {
+                    continue;
+                }
+
+                if y > 0 && is_open((x, y - 1)) {
+                    let below = index - self.width;
+                    let flow = self.flow(FluidGrid::stable_lower(remaining + self.water[below]) - self.water[below], remaining);
+                    next[index] -= flow;
+                    next[below] += flow;
+                    remaining -= flow;
+                }
+
+                for (open, side) in [(x > 0, index.wrapping_sub(1)), (x + 1 < self.width, index + 1)] {
+                    if remaining <= 0. || !open || !is_open((side % self.width, y)) {
+                        continue;
+                    }
+                    let flow = self.flow((remaining - self.water[side]) / 4., remaining);
+                    next[index] -= flow;
+                    next[side] += flow;
+                    remaining -= flow;
+                }
+
+                if remaining > 0. && y + 1 < self.height && is_open((x, y + 1)) {
+                    let above = index + self.width;
+                    let flow = self.flow(remaining - FluidGrid::stable_lower(remaining + self.water[above]), remaining);
+                    next[index] -= flow;
+                    next[above] += flow;
+                }
+            }
+        }
+        self.water = next;
+    }
+
+    fn flow(&self, wanted: f32, remaining: f32) -> f32 {
+        let flow = wanted.clamp(0., remaining.min(MAX_WATER));
+        return if flow < MIN_FLOW { 0. } else { flow };
+    }
+}
+
+pub(crate) fn update_fluid(
+    nav_grid: Res<NavGrid>,
+    mut fluid_grid: ResMut<FluidGrid>,
+) {
+    if fluid_grid.water.len() != nav_grid.size().0 * nav_grid.size().1 {
+        fluid_grid.resize(nav_grid.size());
+    }
+    // The level's terrain is rasterized the frame it's laid out, after `reset`.
+    if nav_grid.is_changed() {
+        for region in std::mem::take(&mut fluid_grid.pending) {
+            fluid_grid.fill(&nav_grid, region);
+        }
+    }
+    fluid_grid.step(|cell| nav_grid.is_open(cell));
+}
+
+pub(crate) fn update_player_in_water(
+    time: Res<Time>,
+    nav_grid: Res<NavGrid>,
+    fluid_grid: Res<FluidGrid>,
+    mut player_query: Query<(&Transform, &mut Velocity, &mut Health), With<Player>>,
+) {
+    for (transform, mut velocity, mut health) in player_query.iter_mut() {
+        let submerged = nav_grid.cell(transform.translation.truncate())
+            .is_some_and(|cell| fluid_grid.water(cell) > SUBMERGED);
+        if submerged {
+            velocity.linvel *= WATER_DRAG;
+            health.0 = (health.0 - WATER_DAMAGE * time.delta_seconds()).max(0.);
+        }
+    }
+}
+
+pub(crate) fn update_fluid_gizmo(
+    nav_grid: Res<NavGrid>,
+    fluid_grid: Res<FluidGrid>,
+    mut gizmos: Gizmos,
+) {
+    for y in 0..fluid_grid.height {
+        for x in 0..fluid_grid.width {
+            let water = fluid_grid.water((x, y)).min(MAX_WATER);
+            if water >= MIN_FLOW {
+                let size = nav_grid.cell_size() * Vec2::new(1., water);
+                let center = nav_grid.center((x, y)) - Vec2::new(0., (nav_grid.cell_size() - size.y) / 2.);
+                gizmos.rect_2d(center, 0., size, Color::BLUE);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Rect;
+    use crate::fluid::FluidGrid;
+
+    fn grid_with(width: usize, height: usize, cells: &[((usize, usize), f32)]) -> FluidGrid {
+        let mut grid = FluidGrid::default();
+        grid.resize((width, height));
+        for ((x, y), water) in cells {
+            grid.water[y * width + x] = *water;
+        }
+        return grid;
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut grid = grid_with(2, 2, &[((0, 0), 1.), ((1, 1), 0.5)]);
+
+        grid.reset(vec![Rect::new(0., 0., 1., 1.)]);
+
+        assert!(grid.water.iter().all(|water| *water == 0.));
+        assert_eq!(grid.pending.len(), 1);
+    }
+
+    #[test]
+    fn test_falls() {
+        let mut grid = grid_with(1, 4, &[((0, 3), 1.)]);
+
+        for _ in 0..8 {
+            grid.step(|_| true);
+        }
+
+        assert!(grid.water((0, 0)) > 0.99);
+        assert!((grid.water.iter().sum::<f32>() - 1.).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_spreads() {
+        let mut grid = grid_with(5, 1, &[((2, 0), 1.)]);
+
+        for _ in 0..64 {
+            grid.step(|_| true);
+        }
+
+        assert!(grid.water((0, 0)) > 0.1);
+        assert!(grid.water((4, 0)) > 0.1);
+        assert!((grid.water.iter().sum::<f32>() - 1.).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_blocked_by_terrain() {
+        let mut grid = grid_with(3, 1, &[((0, 0), 1.)]);
+
+        for _ in 0..64 {
+            grid.step(|(x, _)| x != 1);
+        }
+
+        assert_eq!(grid.water((2, 0)), 0.);
+        assert_eq!(grid.water((0, 0)), 1.);
+    }
+
+    #[test]
+    fn test_floods_opened_cells() {
+        let mut grid = grid_with(3, 1, &[((0, 0), 1.)]);
+        for _ in 0..8 {
+            grid.step(|(x, _)| x != 1);
+        }
+
+        for _ in 0..64 {
+            grid.step(|_| true);
+        }
+
+        assert!(grid.water((2, 0)) > 0.1);
+    }
+}
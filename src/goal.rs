@@ -0,0 +1,45 @@
+use bevy::log::info;
+use bevy::math::Vec2;
+use bevy::prelude::{Color, Commands, Component, Gizmos, Query, Transform, TransformBundle, With};
+use crate::Player;
+
+/// A place a worm has to reach. The level is complete once every goal has been reached.
+#[derive(Component)]
+pub(crate) struct Goal {
+    pub(crate) radius: f32,
+    pub(crate) reached: bool,
+}
+
+pub(crate) fn spawn_goal(commands: &mut Commands, position: Vec2, radius: f32) {
+    commands.spawn(TransformBundle::from_transform(Transform::from_translation(position.extend(0.))))
+        .insert(Goal { radius, reached: false });
+}
+
+/// Marks goals reached as worms arrive at them, announcing when the last one is.
+pub(crate) fn update_goals(
+    player_query: Query<&Transform, With<Player>>,
+    mut goal_query: Query<(&mut Goal, &Transform)>,
+) {
+    let mut newly_reached = false;
+    for (mut goal, transform) in goal_query.iter_mut() {
+        if goal.reached {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        if player_query.iter().any(|player_transform| player_transform.translation.truncate().distance(position) < goal.radius) {
+            goal.reached = true;
+            newly_reached = true;
+            info!("reached the goal at {position}");
+        }
+    }
+    if newly_reached && goal_query.iter().all(|(goal, _)| goal.reached) {
+        info!("level complete");
+    }
+}
+
+pub(crate) fn update_goal_gizmo(goal_query: Query<(&Goal, &Transform)>, mut gizmos: Gizmos) {
+    for (goal, transform) in goal_query.iter() {
+        let color = if goal.reached { Color::GREEN } else { Color::GOLD };
+        gizmos.circle_2d(transform.translation.truncate(), goal.radius, color);
+    }
+}
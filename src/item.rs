@@ -0,0 +1,216 @@
+use bevy::math::{Rect, Vec2};
+use bevy::prelude::{Color, Commands, Component, Entity, EventReader, Gizmos, Query, Res, ResMut, Resource, Transform, TransformBundle, With};
+use bevy_rapier2d::geometry::{ActiveEvents, Collider, ColliderDisabled, Sensor};
+use bevy_rapier2d::pipeline::CollisionEvent;
+use serde::{Deserialize, Serialize};
+use crate::mouth::Mouth;
+use crate::{Player, TerrainCarved};
+use crate::polygon::Polygon;
+use crate::spatial::TerrainIndex;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) enum ItemKind {
+    Food,
+    Gem,
+    PowerUp,
+}
+
+impl ItemKind {
+    fn score(self) -> u32 {
+        return match self {
+            ItemKind::Food => 1,
+            ItemKind::Gem => 10,
+            ItemKind::PowerUp => 5,
+        };
+    }
+
+    fn color(self) -> Color {
+        return match self {
+            ItemKind::Food => Color::GREEN,
+            ItemKind::Gem => Color::CYAN,
+            ItemKind::PowerUp => Color::FUCHSIA,
+        };
+    }
+}
+
+/// How much each power-up grows the mouth of the worm that collects it.
+const POWER_UP_GROWTH: f32 = 1.25;
+
+/// A pickup buried in the terrain. Its collider stays disabled until carving exposes it.
+#[derive(Component)]
+pub(crate) struct Item {
+    pub(crate) kind: ItemKind,
+    pub(crate) revealed: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Resource, Serialize)]
+pub(crate) struct Inventory {
+    pub(crate) score: u32,
+    pub(crate) food: u32,
+    pub(crate) gems: u32,
+    pub(crate) power_ups: u32,
+}
+
+impl Inventory {
+    pub(crate) fn collect(&mut self, kind: ItemKind) {
+        self.score += kind.score();
+        match kind {
+            ItemKind::Food => self.food += 1,
+            ItemKind::Gem => self.gems += 1,
+            ItemKind::PowerUp => self.power_ups += 1,
+        }
+    }
+}
+
+pub(crate) fn spawn_item(commands: &mut Commands, kind: ItemKind, position: Vec2, revealed: bool) {
+    let mut item = commands.spawn(TransformBundle::from_transform(Transform::from_translation(position.extend(0.))));
+    item.insert(Collider::ball(0.5))
+        .insert(Sensor)
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(Item { kind, revealed });
+    if !revealed {
+        item.insert(ColliderDisabled);
+    }
+}
+
+/// Places items on a `spacing` grid inside `polygon`, given in global space. Every seventh item is
+/// a gem and every thirteenth a power-up; the rest are food.
+pub(crate) fn scatter_items(polygon: &Polygon, spacing: f32) -> Vec<(ItemKind, Vec2)> {
+    let aabb = polygon.aabb();
+    let mut items = vec![];
+    let mut y = aabb.min.y + spacing / 2.;
+    while y < aabb.max.y {
+        let mut x = aabb.min.x + spacing / 2.;
+        while x < aabb.max.x {
+            let position = Vec2::new(x, y);
+            if polygon.contains(position) {
+                let kind = match items.len() + 1 {
+                    index if index % 13 == 0 => ItemKind::PowerUp,
+                    index if index % 7 == 0 => ItemKind::Gem,
+                    _ => ItemKind::Food,
+                };
+                items.push((kind, position));
+            }
+            x += spacing;
+        }
+        y += spacing;
+    }
+    return items;
+}
+
+/// Enables the collider of any buried item in a carved region that no terrain `Polygon` covers
+/// anymore.
+pub(crate) fn update_item_reveal(
+    mut commands: Commands,
+    mut carved_events: EventReader<TerrainCarved>,
+    terrain_index: Res<TerrainIndex>,
+    terrain_query: Query<(&Polygon, &Transform)>,
+    mut item_query: Query<(Entity, &mut Item, &Transform)>,
+) {
+    let regions: Vec<Rect> = carved_events.read().map(|carved_event| carved_event.region).collect();
+    if regions.is_empty() {
+        return;
+    }
+
+    for (entity, mut item, transform) in item_query.iter_mut() {
+        let position = transform.translation.truncate();
+        if item.revealed || !regions.iter().any(|region| region.contains(position)) {
+            continue;
+        }
+        let covered = terrain_index.query(Rect::from_center_size(position, Vec2::ZERO)).into_iter()
+            .filter_map(|terrain| terrain_query.get(terrain).ok())
+            .any(|(polygon, terrain_transform)| polygon.to_global_space(terrain_transform).contains(position));
+        if !covered {
+            item.revealed = true;
+            commands.entity(entity).remove::<ColliderDisabled>();
+        }
+    }
+}
+
+pub(crate) fn update_item_pickup(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut inventory: ResMut<Inventory>,
+    mut player_query: Query<&mut Mouth, With<Player>>,
+    item_query: Query<&Item>,
+) {
+    for collision_event in collision_events.read() {
+        let CollisionEvent::Started(a, b, _) = collision_event else { continue };
+        for (player, entity) in [(*a, *b), (*b, *a)] {
+            if let (Ok(mut mouth), Ok(item)) = (player_query.get_mut(player), item_query.get(entity)) {
+                inventory.collect(item.kind);
+                if item.kind == ItemKind::PowerUp {
+                    mouth.upgrade(POWER_UP_GROWTH);
+                }
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+pub(crate) fn update_item_gizmo(
+    item_query: Query<(&Item, &Collider, &Transform)>,
+    mut gizmos: Gizmos,
+) {
+    for (item, collider, transform) in item_query.iter() {
+        if !item.revealed {
+            continue;
+        }
+        let Some(outline) = Polygon::from_collider(collider, 16) else {
+            continue;
+        };
+        let vertices = outline.to_global_space(transform).vertices;
+        for index in 0..vertices.len() {
+            gizmos.line_2d(vertices[index], vertices[(index + 1) % vertices.len()], item.kind.color());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+    use crate::item::{Inventory, ItemKind, scatter_items};
+    use crate::polygon::Polygon;
+
+    #[test]
+    fn test_scatter_inside_polygon() {
+        let polygon = Polygon::from(vec![
+            Vec2::new(0., 0.),
+            Vec2::new(0., 15.),
+            Vec2::new(15., 0.),
+        ]);
+
+        let items = scatter_items(&polygon, 4.);
+
+        assert_eq!(items.len(), 6);
+        assert!(items.iter().all(|(_, position)| polygon.contains(*position)));
+    }
+
+    #[test]
+    fn test_scatter_kinds() {
+        let polygon = Polygon::from(vec![
+            Vec2::new(0., 0.),
+            Vec2::new(0., 13.),
+            Vec2::new(1., 13.),
+            Vec2::new(1., 0.),
+        ]);
+
+        let kinds: Vec<ItemKind> = scatter_items(&polygon, 1.).into_iter().map(|(kind, _)| kind).collect();
+
+        assert_eq!(kinds.len(), 13);
+        assert_eq!(kinds[6], ItemKind::Gem);
+        assert_eq!(kinds[12], ItemKind::PowerUp);
+        assert_eq!(kinds.iter().filter(|kind| **kind == ItemKind::Food).count(), 11);
+    }
+
+    #[test]
+    fn test_collect() {
+        let mut inventory = Inventory::default();
+
+        inventory.collect(ItemKind::Food);
+        inventory.collect(ItemKind::Gem);
+        inventory.collect(ItemKind::Gem);
+
+        assert_eq!(inventory, Inventory { score: 21, food: 1, gems: 2, power_ups: 0 });
+    }
+}
@@ -0,0 +1,270 @@
+use std::fs;
+use std::path::Path;
+use bevy::asset::{Asset, AssetLoader, AsyncReadExt, LoadContext};
+use bevy::asset::io::Reader;
+use bevy::math::{Rect, Vec2};
+use bevy::prelude::{Color, Component, Transform};
+use bevy::reflect::TypePath;
+use bevy::utils::BoxedFuture;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use crate::asset::AssetError;
+use crate::enemy::EnemyBehavior;
+use crate::item::ItemKind;
+use crate::polygon::Polygon;
+
+/// The level file the editor writes, and the same file as the asset server sees it.
+pub(crate) const LEVEL_PATH: &str = "assets/main.level.ron";
+pub(crate) const LEVEL_ASSET_PATH: &str = "main.level.ron";
+/// Bounds of a level that doesn't give its own.
+pub(crate) const DEFAULT_BOUNDS: Rect = Rect {
+    min: Vec2::new(-32., -96.),
+    max: Vec2::new(96., 32.),
+};
+
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub(crate) enum TerrainMaterial {
+    #[default]
+    Dirt,
+    Clay,
+    Rock,
+}
+
+impl TerrainMaterial {
+    pub(crate) fn color(&self) -> Color {
+        return match self {
+            TerrainMaterial::Dirt => Color::ORANGE,
+            TerrainMaterial::Clay => Color::ORANGE_RED,
+            TerrainMaterial::Rock => Color::GRAY,
+        };
+    }
+
+    pub(crate) fn next(&self) -> TerrainMaterial {
+        return match self {
+            TerrainMaterial::Dirt => TerrainMaterial::Clay,
+            TerrainMaterial::Clay => TerrainMaterial::Rock,
+            TerrainMaterial::Rock => TerrainMaterial::Dirt,
+        };
+    }
+}
+
+/// How a level's terrain is represented and carved.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub(crate) enum TerrainBackend {
+    /// Each terrain `Polygon` is carved directly.
+    #[default]
+    Polygon,
+    /// Terrain is resampled into `DensityChunk`s with `resolution` cells per side, carved there and
+    /// outlined from them.
+    Density { resolution: usize },
+}
+
+/// Everything a new game starts with, authored in the editor or by hand, used instead of the
+/// generated level. Loaded as an asset, so edits to the file reload the level while it's played.
+#[derive(Asset, Clone, Debug, Default, Deserialize, PartialEq, Serialize, TypePath)]
+pub(crate) struct Level {
+    #[serde(default)]
+    pub(crate) backend: TerrainBackend,
+    pub(crate) terrain: Vec<LevelTerrain>,
+    #[serde(default)]
+    pub(crate) bitmaps: Vec<LevelBitmap>,
+    /// Masks traced into polygon terrain, for levels painted rather than built in the editor.
+    #[serde(default)]
+    pub(crate) masks: Vec<LevelBitmap>,
+    /// Where each local player's worm starts, by `Player` index. Worms past the end start where
+    /// they would without a level.
+    #[serde(default)]
+    pub(crate) spawns: Vec<Vec2>,
+    /// Items placed by hand. A level without any has items scattered through its terrain instead.
+    #[serde(default)]
+    pub(crate) items: Vec<LevelItem>,
+    #[serde(default)]
+    pub(crate) enemies: Vec<LevelEnemy>,
+    #[serde(default)]
+    pub(crate) goals: Vec<LevelGoal>,
+    /// Regions filled with water wherever the terrain leaves them open.
+    #[serde(default)]
+    pub(crate) water: Vec<Rect>,
+    /// Area the cameras, debris and enemies are kept in. `DEFAULT_BOUNDS` if not given.
+    #[serde(default)]
+    pub(crate) bounds: Option<Rect>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct LevelTerrain {
+    pub(crate) polygon: Polygon,
+    pub(crate) transform: Transform,
+    #[serde(default)]
+    pub(crate) material: TerrainMaterial,
+}
+
+/// PNG laid into the level as terrain, alongside any polygon terrain.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct LevelBitmap {
+    /// Read as a `Mask`.
+    pub(crate) path: String,
+    /// Places the image's center; its scale is the size of a pixel in world units.
+    pub(crate) transform: Transform,
+    #[serde(default)]
+    pub(crate) material: TerrainMaterial,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct LevelItem {
+    pub(crate) kind: ItemKind,
+    pub(crate) position: Vec2,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct LevelEnemy {
+    pub(crate) behavior: EnemyBehavior,
+    pub(crate) speed: f32,
+    pub(crate) position: Vec2,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct LevelGoal {
+    pub(crate) position: Vec2,
+    pub(crate) radius: f32,
+}
+
+impl Level {
+    pub(crate) fn to_ron(&self) -> Result<String, AssetError> {
+        return Ok(ron::ser::to_string_pretty(self, PrettyConfig::default())?);
+    }
+
+    pub(crate) fn bounds(&self) -> Rect {
+        return self.bounds.unwrap_or(DEFAULT_BOUNDS);
+    }
+
+    /// Parses a level, rejecting terrain too degenerate to carve or place items in, density chunks
+    /// with no cells and bounds with no area.
+    pub(crate) fn from_ron(source: &str) -> Result<Level, AssetError> {
+        let level: Level = ron::from_str(source)?;
+        if let Some(index) = level.terrain.iter().position(|terrain| terrain.polygon.vertices.len() < 3) {
+            return Err(AssetError::Invalid(format!("terrain {index} has fewer than 3 vertices")));
+        }
+        if level.backend == (TerrainBackend::Density { resolution: 0 }) {
+            return Err(AssetError::Invalid("density resolution is 0".to_string()));
+        }
+        if level.bounds().width() <= 0. || level.bounds().height() <= 0. {
+            return Err(AssetError::Invalid(format!("bounds {:?} have no area", level.bounds())));
+        }
+        return Ok(level);
+    }
+
+    pub(crate) fn write(&self, path: impl AsRef<Path>) -> Result<(), AssetError> {
+        return Ok(fs::write(path, self.to_ron()?)?);
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = AssetError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Level, AssetError>> {
+        return Box::pin(async move {
+            let mut source = String::new();
+            reader.read_to_string(&mut source).await?;
+            return Level::from_ron(&source);
+        });
+    }
+
+    fn extensions(&self) -> &[&str] {
+        return &["level.ron"];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use bevy::math::{Rect, Vec2, Vec3};
+    use bevy::prelude::Transform;
+    use crate::asset::AssetError;
+    use crate::enemy::EnemyBehavior;
+    use crate::item::ItemKind;
+    use crate::level::{DEFAULT_BOUNDS, LEVEL_PATH, Level, LevelBitmap, LevelEnemy, LevelGoal, LevelItem, LevelTerrain, TerrainBackend, TerrainMaterial};
+    use crate::polygon::Polygon;
+
+    #[test]
+    fn test_round_trip_file() {
+        let expected = Level {
+            backend: TerrainBackend::Density { resolution: 32 },
+            terrain: vec![LevelTerrain {
+                polygon: Polygon::rectangle(Vec2::ONE),
+                transform: Transform::from_xyz(32., -32., 0.)
+                    .with_scale(Vec3::splat(64.)),
+                material: TerrainMaterial::Rock,
+            }],
+            bitmaps: vec![LevelBitmap {
+                path: "assets/level.png".to_string(),
+                transform: Transform::from_xyz(0., -64., 0.)
+                    .with_scale(Vec3::splat(0.25)),
+                material: TerrainMaterial::Clay,
+            }],
+            masks: vec![],
+            spawns: vec![Vec2::new(-4., 4.)],
+            items: vec![LevelItem { kind: ItemKind::Gem, position: Vec2::new(16., -8.) }],
+            enemies: vec![LevelEnemy { behavior: EnemyBehavior::Flee(16), speed: 12., position: Vec2::new(80., 8.) }],
+            goals: vec![LevelGoal { position: Vec2::new(60., -60.), radius: 4. }],
+            water: vec![Rect::new(44., -12., 60., 0.)],
+            bounds: Some(Rect::new(-16., -80., 80., 16.)),
+        };
+        let path = "target/test_level_round_trip_file.level.ron";
+
+        expected.write(path).unwrap();
+        let actual = Level::from_ron(&fs::read_to_string(path).unwrap()).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_missing_material_default() {
+        let actual = Level::from_ron("(terrain: [(polygon: (vertices: [(0, 0), (0, 1), (1, 0)]), transform: (translation: (0, 0, 0), rotation: (0, 0, 0, 1), scale: (1, 1, 1)))])").unwrap();
+
+        assert_eq!(actual.backend, TerrainBackend::Polygon);
+        assert!(actual.bitmaps.is_empty());
+        assert!(actual.enemies.is_empty());
+        assert!(actual.water.is_empty());
+        assert_eq!(actual.bounds(), DEFAULT_BOUNDS);
+        assert_eq!(actual.terrain[0].material, TerrainMaterial::Dirt);
+    }
+
+    #[test]
+    fn test_degenerate_terrain_rejected() {
+        let actual = Level::from_ron("(terrain: [(polygon: (vertices: []), transform: (translation: (0, 0, 0), rotation: (0, 0, 0, 1), scale: (1, 1, 1)))])");
+
+        assert!(matches!(actual, Err(AssetError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_zero_density_resolution_rejected() {
+        let actual = Level::from_ron("(backend: Density(resolution: 0), terrain: [])");
+
+        assert!(matches!(actual, Err(AssetError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_empty_bounds_rejected() {
+        let actual = Level::from_ron("(terrain: [], bounds: Some((min: (0, 0), max: (16, 0))))");
+
+        assert!(matches!(actual, Err(AssetError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_shipped_level() {
+        let actual = Level::from_ron(&fs::read_to_string(LEVEL_PATH).unwrap()).unwrap();
+
+        assert_eq!(actual.bounds(), DEFAULT_BOUNDS);
+        assert!(!actual.terrain.is_empty());
+    }
+}
@@ -1,168 +1,702 @@
+#![allow(clippy::needless_return)]
+
+mod asset;
+mod bitmap;
+mod camera;
+mod carve;
+mod contour;
+mod controls;
+mod debris;
+mod density;
+mod editor;
+mod enemy;
+mod fluid;
+mod goal;
+mod item;
+mod level;
+mod mouth;
+mod navigation;
+mod net;
+mod particle;
 mod polygon;
 mod polygon_transform_bundle;
+mod save;
+mod spatial;
+mod tuning;
 
-use bevy::app::{App, Startup, Update};
+use std::net::{Ipv4Addr, SocketAddr};
+use bevy::app::{App, FixedUpdate, Last, PostUpdate, Startup, Update};
+use bevy::asset::{AssetApp, AssetEvent, AssetServer, Assets, Handle, LoadState};
 use bevy::DefaultPlugins;
 use bevy::input::ButtonState;
 use bevy::input::keyboard::KeyboardInput;
-use bevy::math::{Vec2, Vec3};
-use bevy::prelude::{Camera, Camera2dBundle, Color, Commands, Component, Entity, EventReader, Gizmos, GlobalTransform, KeyCode, OrthographicProjection, Query, Transform, TransformBundle, With};
-use bevy::utils::default;
+use bevy::input::mouse::{MouseButton, MouseButtonInput};
+use bevy::log::{info, warn};
+use bevy::math::{Rect, Vec2, Vec3};
+use bevy::prelude::{not, Camera, Changed, Color, Commands, Component, Entity, Event, EventReader, EventWriter, Gizmos, GlobalTransform, Has, Image, IntoSystemConfigs, KeyCode, Or, Query, Res, ResMut, Resource, Time, Transform, TransformBundle, With, Without};
+use bevy::transform::TransformSystem;
 use bevy::window::{PrimaryWindow, Window};
 use bevy_rapier2d::dynamics::RigidBody;
-use bevy_rapier2d::geometry::Collider;
-use bevy_rapier2d::plugin::{NoUserData, RapierPhysicsPlugin};
+use bevy_rapier2d::geometry::{Collider, CollisionGroups, Group};
+use bevy_rapier2d::plugin::{NoUserData, PhysicsSet, RapierPhysicsPlugin};
 use bevy_rapier2d::prelude::{GravityScale, Velocity};
 use bevy_rapier2d::render::RapierDebugRenderPlugin;
+use crate::bitmap::{BitmapTile, Mask, TERRAIN_MASK_PATH, carve_bitmap, spawn_bitmap_terrain, spawn_bitmap_tile};
+use crate::camera::{LevelBounds, PlayerCamera, cursor_to_world, startup_camera, update_camera_follow, update_camera_viewports, update_camera_zoom};
+use crate::carve::{Cut, carve_parallel};
+use crate::contour::{Contour, update_contours};
+use crate::controls::{ControlScheme, Controls, InputBinding};
+use crate::debris::{Debris, SUPPORT_DISTANCE, is_supported, spawn_debris, update_debris_bounds, update_debris_collider};
+use crate::density::{DensityChunk, carve_density, spawn_density_chunk, spawn_density_terrain};
+use crate::editor::{Editor, PICK_RADIUS, is_editing, update_editor};
+use crate::enemy::{Enemy, EnemyBehavior, spawn_enemy, update_enemies};
+use crate::fluid::{FluidGrid, update_fluid, update_fluid_gizmo, update_player_in_water};
+use crate::goal::{Goal, spawn_goal, update_goal_gizmo, update_goals};
+use crate::item::{Inventory, Item, scatter_items, spawn_item, update_item_gizmo, update_item_pickup, update_item_reveal};
+use crate::level::{DEFAULT_BOUNDS, LEVEL_ASSET_PATH, Level, LevelEnemy, LevelLoader, LevelTerrain, TerrainBackend, TerrainMaterial};
+use crate::mouth::{LastCarve, MOUTH_PATH, Mouth};
+use crate::navigation::{NavGrid, startup_nav_grid, update_nav_grid};
+use crate::net::{NetClient, NetRole, NetServer, NetSocket, is_client, update_client_receive, update_client_send, update_remote_worms, update_server_receive, update_server_send, update_terrain_ids};
+use crate::particle::{ParticlePool, ParticleSettings, update_carve_particles, update_particles};
 use crate::polygon::Polygon;
 use crate::polygon_transform_bundle::PolygonTransformBundle;
+use crate::save::{SAVE_PATH, SaveGame, update_save};
+use crate::spatial::{TerrainIndex, overlaps, update_terrain_index};
+use crate::tuning::{Tuning, TuningLoader, startup_tuning, update_tuning};
 
 fn main() {
-    App::new()
+    let net_role = NetRole::from_args(std::env::args());
+    let mut app = App::new();
+    match SaveGame::load_or_default(SAVE_PATH) {
+        Some(save) => {
+            app.insert_resource(save.inventory.clone());
+            app.insert_resource(save);
+        }
+        None => {
+            app.init_resource::<Inventory>();
+        }
+    }
+
+    app
         .add_plugins(DefaultPlugins)
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.))
         .add_plugins(RapierDebugRenderPlugin::default())
 
+        .init_resource::<Tuning>()
+        .init_asset::<Tuning>()
+        .init_asset_loader::<TuningLoader>()
+        .add_systems(Startup, startup_tuning)
+        .add_systems(Update, update_tuning)
+
+        .insert_resource(LevelBounds(DEFAULT_BOUNDS))
+        .insert_resource(match net_role {
+            // A client plays the one worm it predicts.
+            Some(NetRole::Client(_)) => LocalPlayers(1),
+            _ => LocalPlayers::from_args(std::env::args()),
+        })
         .add_systems(Startup, startup_camera)
+        .add_systems(Update, (update_camera_zoom, update_camera_viewports))
+        .add_systems(PostUpdate, update_camera_follow
+            .after(PhysicsSet::Writeback)
+            .before(TransformSystem::TransformPropagate))
+        .init_resource::<ControlScheme>()
         .add_systems(Startup, startup_player)
-        .add_systems(Update, update_player)
+        .add_systems(Update, update_player.run_if(not(is_editing)))
 
-        .add_systems(Startup, startup_terrain)
-        .add_systems(Update, (update_terrain, update_terrain_gizmo))
+        .init_asset::<Level>()
+        .init_asset_loader::<LevelLoader>()
+        .add_systems(Startup, startup_terrain.run_if(not(is_client)))
+        .add_systems(Update, update_level.run_if(not(is_client)).before(update_terrain_index))
+        .add_systems(Update, (update_goals, update_goal_gizmo))
+        .add_systems(Update, (update_terrain.run_if(not(is_editing)).run_if(not(is_client)), update_terrain_gizmo))
+        .add_systems(Update, (update_debris_collider.after(update_terrain), update_debris_bounds))
+        .add_systems(Update, update_terrain_collider.after(update_terrain).after(update_level).after(update_editor))
+        .add_systems(Update, (update_contours::<DensityChunk>, update_contours::<BitmapTile>).after(update_terrain))
+        .init_resource::<TerrainIndex>()
+        .add_systems(Update, update_terrain_index.before(update_terrain).before(update_item_reveal))
 
-        .run();
-}
+        .add_event::<TerrainCarved>()
 
-fn startup_camera(mut commands: Commands) {
-    commands.spawn(Camera2dBundle {
-        projection: OrthographicProjection {
-            scale: 1. / 16.,
-            ..default()
+        .init_resource::<ParticleSettings>()
+        .init_resource::<ParticlePool>()
+        .add_systems(Update, (update_carve_particles.after(update_terrain), update_particles))
+
+        .add_systems(Startup, startup_nav_grid)
+        .add_systems(Update, (update_nav_grid.after(update_terrain), update_enemies.after(update_nav_grid)))
+
+        .init_resource::<FluidGrid>()
+        .add_systems(FixedUpdate, update_fluid)
+        .add_systems(Update, (update_player_in_water.after(update_player), update_fluid_gizmo))
+        .add_systems(Update, (update_player_health.after(update_player_in_water), update_health_gizmo))
+
+        .add_systems(Update, (
+            update_item_reveal.after(update_terrain),
+            update_item_pickup,
+            update_item_gizmo,
+        ))
+
+        .init_resource::<Editor>()
+        .add_systems(Update, update_editor)
+
+        .add_systems(Last, update_save.run_if(not(is_client)));
+
+    match net_role {
+        Some(NetRole::Server(address)) => match NetSocket::bind(address) {
+            Ok(socket) => {
+                info!("listening on {}", socket.local_addr().unwrap_or(address));
+                app
+                    .insert_resource(NetServer::from(socket))
+                    .add_systems(Update, (update_server_receive, update_remote_worms.after(update_server_receive)).before(update_terrain))
+                    .add_systems(Last, (update_terrain_ids, update_server_send).chain());
+            }
+            Err(error) => warn!("could not listen on {address}: {error}"),
         },
-        ..default()
-    });
+        Some(NetRole::Client(server)) => match NetSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)) {
+            Ok(socket) => {
+                app
+                    .insert_resource(NetClient::new(socket, server))
+                    .add_systems(Update, (update_client_receive, update_client_send.after(update_player)));
+            }
+            Err(error) => warn!("could not connect to {server}: {error}"),
+        },
+        None => {}
+    }
+
+    app.run();
+}
+
+fn startup_player(mut commands: Commands, tuning: Res<Tuning>, local_players: Res<LocalPlayers>, save: Option<Res<SaveGame>>) {
+    for index in 0..local_players.0 {
+        let (transform, velocity, mouth, health) = match save.as_ref().and_then(|save| save.players.get(index)) {
+            Some(player) => (
+                player.transform,
+                player.velocity(),
+                player.mouth.clone().unwrap_or_else(|| Mouth::load_or_default(MOUTH_PATH)),
+                player.health,
+            ),
+            None => (
+                Transform::from_translation(spawn_point(None, index).extend(0.)),
+                Velocity::default(),
+                Mouth::load_or_default(MOUTH_PATH),
+                MAX_HEALTH,
+            ),
+        };
+
+        let worm = spawn_worm(&mut commands, &tuning, index, transform, velocity, mouth, health);
+        commands.entity(worm).insert(InputBinding::for_player(index));
+    }
 }
 
-fn startup_player(mut commands: Commands) {
-    commands.spawn(RigidBody::Dynamic)
-        .insert(TransformBundle::from_transform(Transform::from_xyz(-4., 4., 0.)))
-        .insert(GravityScale(0.))
-        .insert(Velocity::default())
-        .insert(Collider::cuboid(2., 2.))
+pub(crate) fn spawn_worm(commands: &mut Commands, tuning: &Tuning, index: usize, transform: Transform, velocity: Velocity, mouth: Mouth, health: f32) -> Entity {
+    return commands.spawn(RigidBody::Dynamic)
+        .insert(TransformBundle::from_transform(transform))
+        .insert(GravityScale(tuning.gravity_scale))
+        .insert(velocity)
+        .insert(tuning.collider())
+        .insert(CollisionGroups::new(WORM_GROUP, Group::ALL))
         .insert(Controls::default())
-        .insert(Player);
+        .insert(mouth)
+        .insert(LastCarve::default())
+        .insert(Health(health))
+        .insert(Player(index))
+        .id();
 }
 
-#[derive(Component, Default)]
-struct Controls {
-    left: bool,
-    right: bool,
-    action: bool,
-    up: bool,
-    down: bool,
+/// Number of worms playing split-screen on this machine.
+#[derive(Debug, PartialEq, Resource)]
+struct LocalPlayers(usize);
+
+impl LocalPlayers {
+    /// Reads `--players <count>` from the command line. One player unless it asks for more.
+    fn from_args(mut args: impl Iterator<Item = String>) -> LocalPlayers {
+        while let Some(arg) = args.next() {
+            if arg != "--players" {
+                continue;
+            }
+            return match args.next().map(|count| count.parse()) {
+                Some(Ok(count)) if count > 0 => LocalPlayers(count),
+                _ => {
+                    warn!("--players needs a count of at least 1");
+                    LocalPlayers(1)
+                }
+            };
+        }
+        return LocalPlayers(1);
+    }
 }
 
+/// A local player's worm, numbered from 0.
 #[derive(Component)]
-struct Player;
+struct Player(usize);
+
+/// Health a worm starts with, and comes back with after dying.
+pub(crate) const MAX_HEALTH: f32 = 100.;
 
+#[derive(Component)]
+struct Health(f32);
+
+/// Where a local player's worm starts: the level's spawn point for it if it has one, else a spot
+/// above the generated terrain.
+fn spawn_point(level: Option<&Level>, index: usize) -> Vec2 {
+    return level.and_then(|level| level.spawns.get(index).copied())
+        .unwrap_or(Vec2::new(-4., 4. - 12. * index as f32));
+}
+
+/// Sends worms whose health ran out back to their spawn point with full health.
+fn update_player_health(
+    current_level: Option<Res<CurrentLevel>>,
+    levels: Res<Assets<Level>>,
+    mut player_query: Query<(&Player, &mut Health, &mut Transform, &mut Velocity)>,
+) {
+    let level = current_level.and_then(|current_level| levels.get(&current_level.handle));
+    for (player, mut health, mut transform, mut velocity) in player_query.iter_mut() {
+        if health.0 > 0. {
+            continue;
+        }
+        info!("worm {} died", player.0);
+        health.0 = MAX_HEALTH;
+        *transform = Transform::from_translation(spawn_point(level, player.0).extend(0.));
+        *velocity = Velocity::default();
+    }
+}
+
+/// Bar above each worm showing how much health it has left.
+fn update_health_gizmo(player_query: Query<(&Health, &Transform)>, mut gizmos: Gizmos) {
+    for (health, transform) in player_query.iter() {
+        let start = transform.translation.truncate() + Vec2::new(-2., 3.);
+        let fraction = health.0 / MAX_HEALTH;
+        let color = if fraction > 0.25 { Color::GREEN } else { Color::RED };
+        gizmos.line_2d(start, start + Vec2::new(4., 0.), Color::DARK_GRAY);
+        gizmos.line_2d(start, start + Vec2::new(4. * fraction, 0.), color);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn update_player(
     mut keyboard_events: EventReader<KeyboardInput>,
-    camera_query: Query<(&Camera, &GlobalTransform)>,
-    mut player_query: Query<(&mut Controls, &mut Velocity, &mut Transform), With<Player>>,
+    mut mouse_button_events: EventReader<MouseButtonInput>,
+    mut control_scheme: ResMut<ControlScheme>,
+    tuning: Res<Tuning>,
+    time: Res<Time>,
+    camera_query: Query<(&PlayerCamera, &Camera, &GlobalTransform)>,
+    mut player_query: Query<(&Player, &InputBinding, &mut Controls, &mut Velocity, &mut Transform)>,
     window_query: Query<&Window, With<PrimaryWindow>>,
 ) {
-    let (camera, camera_transform) = camera_query.single();
-    let (mut player_controls, mut player_velocity, mut player_transform) = player_query.single_mut();
-
+    let mut key_presses = vec![];
     for keyboard_event in keyboard_events.read() {
         match (keyboard_event.key_code, keyboard_event.state) {
-            (Some(KeyCode::W), ButtonState::Pressed) => { player_controls.up = true }
-            (Some(KeyCode::W), ButtonState::Released) => { player_controls.up = false }
-            (Some(KeyCode::A), ButtonState::Pressed) => { player_controls.left = true }
-            (Some(KeyCode::A), ButtonState::Released) => { player_controls.left = false }
-            (Some(KeyCode::S), ButtonState::Pressed) => { player_controls.down = true }
-            (Some(KeyCode::S), ButtonState::Released) => { player_controls.down = false }
-            (Some(KeyCode::D), ButtonState::Pressed) => { player_controls.right = true }
-            (Some(KeyCode::D), ButtonState::Released) => { player_controls.right = false }
-            (Some(KeyCode::Space), ButtonState::Pressed) => { player_controls.action = true }
-            (Some(KeyCode::Space), ButtonState::Released) => { player_controls.action = false }
+            (Some(KeyCode::Tab), ButtonState::Pressed) => { *control_scheme = control_scheme.next() }
+            (Some(key_code), state) => { key_presses.push((key_code, state == ButtonState::Pressed)) }
             _ => {}
         }
     }
 
-    let left = if player_controls.left { Vec2::NEG_X } else { Vec2::ZERO };
-    let right = if player_controls.right { Vec2::X } else { Vec2::ZERO };
-    let up = if player_controls.up { Vec2::Y } else { Vec2::ZERO };
-    let down = if player_controls.down { Vec2::NEG_Y } else { Vec2::ZERO };
-    player_velocity.linvel = 16. * (left + right + up + down);
+    let mut pointer = None;
+    for mouse_button_event in mouse_button_events.read() {
+        if mouse_button_event.button == MouseButton::Left {
+            pointer = Some(mouse_button_event.state == ButtonState::Pressed);
+        }
+    }
+    let cursor_position = window_query.single().cursor_position();
+
+    for (player, binding, mut player_controls, mut player_velocity, mut player_transform) in player_query.iter_mut() {
+        for (key_code, pressed) in key_presses.iter() {
+            binding.apply(&mut player_controls, *key_code, *pressed);
+        }
+
+        if binding.mouse {
+            if let Some(pointer) = pointer {
+                player_controls.pointer = pointer;
+            }
+            player_controls.cursor = camera_query.iter()
+                .find(|(player_camera, _, _)| player_camera.0 == player.0)
+                .zip(cursor_position)
+                .and_then(|((_, camera, camera_transform), cursor_position)| cursor_to_world(camera, camera_transform, cursor_position));
+        }
+
+        let motion = control_scheme.for_binding(Some(binding)).motion(&player_controls, &player_transform, tuning.player_speed, tuning.player_turn_speed, time.delta_seconds());
+        player_velocity.linvel = motion.linvel;
+        player_transform.rotate_z(motion.rotation);
+    }
+}
+
+/// Restores the saved terrain, if there is a save, and starts loading the level.
+fn startup_terrain(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    save: Option<Res<SaveGame>>,
+) {
+    if let Some(save) = &save {
+        for terrain in save.terrain.iter() {
+            if terrain.debris {
+                spawn_debris(&mut commands, PolygonTransformBundle::from((terrain.polygon.clone(), terrain.transform)));
+                continue;
+            }
+            spawn_terrain(&mut commands, terrain.polygon.clone(), terrain.transform, terrain.material);
+        }
+        for chunk in save.chunks.iter() {
+            spawn_density_chunk(&mut commands, chunk.chunk.clone(), chunk.transform, chunk.material);
+        }
+        for tile in save.tiles.iter() {
+            spawn_bitmap_tile(&mut commands, &mut images, tile.mask.clone(), tile.transform, tile.material);
+        }
+        for item in save.items.iter() {
+            spawn_item(&mut commands, item.kind, item.position, item.revealed);
+        }
+    }
+
+    commands.insert_resource(CurrentLevel {
+        handle: asset_server.load(LEVEL_ASSET_PATH),
+        level: Level::default(),
+        from_save: save.is_some(),
+        spawned: false,
+    });
+}
+
+/// The level being played, kept loaded so edits to its file are picked up while the game runs.
+#[derive(Resource)]
+pub(crate) struct CurrentLevel {
+    handle: Handle<Level>,
+    /// The level as last laid out, which the editor saves its terrain into.
+    pub(crate) level: Level,
+    /// Whether the game started from a save, which brings its own terrain, items and worms.
+    from_save: bool,
+    spawned: bool,
+}
+
+/// Lays out the level once it loads, or the generated level if there is no level file, and lays it
+/// out afresh, worms back at their spawn points, whenever the file changes.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn update_level(
+    mut commands: Commands,
+    mut level_events: EventReader<AssetEvent<Level>>,
+    asset_server: Res<AssetServer>,
+    levels: Res<Assets<Level>>,
+    mut images: ResMut<Assets<Image>>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut fluid_grid: ResMut<FluidGrid>,
+    mut bounds: ResMut<LevelBounds>,
+    mut nav_grid: ResMut<NavGrid>,
+    mut player_query: Query<(&Player, &mut Transform, &mut Velocity)>,
+    spawned_query: Query<Entity, Or<(With<TerrainMaterial>, With<Debris>, With<Item>, With<Enemy>, With<Goal>)>>,
+) {
+    let handle = &current_level.handle;
+    let changed = level_events.read()
+        .any(|event| event.is_loaded_with_dependencies(handle) || event.is_modified(handle));
+    let level = if changed {
+        let Some(level) = levels.get(handle) else {
+            return;
+        };
+        level.clone()
+    } else if !current_level.spawned && asset_server.load_state(handle) == LoadState::Failed {
+        generated_level()
+    } else {
+        return;
+    };
 
-    if let Some(cursor_point) = window_query
-        .single()
-        .cursor_position()
-        .and_then(|cursor_position| camera.viewport_to_world_2d(camera_transform, cursor_position))
-    {
-        let old_forward = player_transform.right().truncate();
-        let new_forward = cursor_point - player_transform.translation.truncate();
-        player_transform.rotate_z(old_forward.angle_between(new_forward));
+    if current_level.spawned {
+        for entity in spawned_query.iter() {
+            commands.entity(entity).despawn();
+        }
+    }
+    if current_level.spawned || !current_level.from_save {
+        spawn_level_terrain(&mut commands, &mut images, &level);
+        for (player, mut transform, mut velocity) in player_query.iter_mut() {
+            if let Some(spawn) = level.spawns.get(player.0) {
+                *transform = Transform::from_translation(spawn.extend(0.));
+                *velocity = Velocity::default();
+            }
+        }
     }
+    for enemy in level.enemies.iter() {
+        spawn_enemy(&mut commands, Enemy::new(enemy.behavior, enemy.speed), Transform::from_translation(enemy.position.extend(0.)));
+    }
+    for goal in level.goals.iter() {
+        spawn_goal(&mut commands, goal.position, goal.radius);
+    }
+    // The grid starts out open and is rasterized again once the terrain spawned above is in.
+    bounds.0 = level.bounds();
+    *nav_grid = NavGrid::new(bounds.0, nav_grid.cell_size());
+    fluid_grid.reset(level.water.clone());
+    current_level.level = level;
+    current_level.spawned = true;
 }
 
-fn startup_terrain(mut commands: Commands) {
-    commands.spawn(RigidBody::Fixed)
-        .insert(Polygon::from(vec![
-            Vec2::new(-0.5, -0.5),
-            Vec2::new(-0.5, 0.5),
-            Vec2::new(0.5, 0.5),
-            Vec2::new(0.5, -0.5),
-        ]))
-        .insert(TransformBundle::from_transform(
-            Transform::from_xyz(32., -32., 0.)
-                .with_scale(Vec3::splat(64.))
-        ));
+/// The level played without a level file: terrain traced from the painted mask if there is one,
+/// else a block of dirt with a pond dug into it, and a pair of enemies.
+fn generated_level() -> Level {
+    let enemies = vec![
+        LevelEnemy { behavior: EnemyBehavior::Chase, speed: 8., position: Vec2::new(-16., -16.) },
+        LevelEnemy { behavior: EnemyBehavior::Flee(16), speed: 12., position: Vec2::new(80., 8.) },
+    ];
+
+    // A painted mask stands in for the generated terrain, stretched across the same square.
+    if let Some(mask) = Mask::load(TERRAIN_MASK_PATH) {
+        let transform = Transform::from_xyz(32., -32., 0.)
+            .with_scale(Vec3::splat(64. / mask.width.max(mask.height) as f32));
+        let terrain = mask.trace().into_iter()
+            .map(|polygon| LevelTerrain { polygon, transform, material: TerrainMaterial::default() })
+            .collect();
+        return Level { terrain, enemies, ..Level::default() };
+    }
+
+    let polygon = Polygon::rectangle(Vec2::ONE);
+    let transform = Transform::from_xyz(32., -32., 0.)
+        .with_scale(Vec3::splat(64.));
+
+    let pond_cutter = Rect::from_corners(POND.min, POND.max + Vec2::Y);
+    let pond = PolygonTransformBundle::from((Polygon::from(pond_cutter), Transform::IDENTITY));
+    let terrain = PolygonTransformBundle::from((polygon, transform)).sink(&pond).into_iter()
+        .map(|piece| LevelTerrain { polygon: piece.polygon, transform, material: TerrainMaterial::default() })
+        .collect();
+
+    return Level {
+        terrain,
+        enemies,
+        water: vec![POND],
+        ..Level::default()
+    };
 }
 
+/// Spawns the level's terrain in each of its forms, and its items.
+fn spawn_level_terrain(commands: &mut Commands, images: &mut Assets<Image>, level: &Level) {
+    let mut terrain = level.terrain.clone();
+    for mask in level.masks.iter() {
+        match Mask::read(&mask.path) {
+            Ok(image) => terrain.extend(image.trace().into_iter()
+                .map(|polygon| LevelTerrain { polygon, transform: mask.transform, material: mask.material })),
+            Err(error) => warn!("{}: {error}", mask.path),
+        }
+    }
+
+    for terrain in terrain {
+        let global_polygon = terrain.polygon.to_global_space(&terrain.transform);
+        if level.items.is_empty() {
+            for (kind, position) in scatter_items(&global_polygon, 12.) {
+                spawn_item(commands, kind, position, false);
+            }
+        }
+        match level.backend {
+            TerrainBackend::Polygon => {
+                spawn_terrain(commands, terrain.polygon, terrain.transform, terrain.material);
+            }
+            TerrainBackend::Density { resolution } => {
+                spawn_density_terrain(commands, &global_polygon, terrain.material, resolution);
+            }
+        }
+    }
+    for bitmap in level.bitmaps.iter() {
+        match Mask::read(&bitmap.path) {
+            Ok(mask) => spawn_bitmap_terrain(commands, images, &mask, bitmap.transform, bitmap.material),
+            Err(error) => warn!("{}: {error}", bitmap.path),
+        }
+    }
+    for item in level.items.iter() {
+        spawn_item(commands, item.kind, item.position, false);
+    }
+}
+
+/// Collision group of terrain, which debris and soil land on but worms dig through.
+pub(crate) const TERRAIN_GROUP: Group = Group::GROUP_1;
+/// Collision group of worms.
+pub(crate) const WORM_GROUP: Group = Group::GROUP_2;
+
+pub(crate) fn spawn_terrain(commands: &mut Commands, polygon: Polygon, transform: Transform, material: TerrainMaterial) -> Entity {
+    return commands.spawn(RigidBody::Fixed)
+        .insert(polygon)
+        .insert(TransformBundle::from_transform(transform))
+        .insert(CollisionGroups::new(TERRAIN_GROUP, Group::ALL - WORM_GROUP))
+        .insert(material)
+        .id();
+}
+
+/// Rebuilds the collider of any terrain that was spawned, carved or edited. Contours bring their
+/// own.
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_terrain_collider(
+    mut commands: Commands,
+    terrain_query: Query<(Entity, &Polygon), (Changed<Polygon>, Without<Debris>, Without<Contour>)>,
+) {
+    for (entity, polygon) in terrain_query.iter() {
+        if polygon.vertices.len() < 3 {
+            commands.entity(entity).remove::<Collider>();
+            continue;
+        }
+        commands.entity(entity).insert(polygon.collider());
+    }
+}
+
+/// Pit dug into the top of the terrain at startup and filled with water.
+const POND: Rect = Rect {
+    min: Vec2::new(44., -12.),
+    max: Vec2::new(60., 0.),
+};
+
+/// A carver's mouth cut into a terrain entity.
+#[derive(Clone, Debug, Event)]
+pub(crate) struct TerrainCarved {
+    /// The terrain carved. It has been despawned if nothing of it was left.
+    pub(crate) entity: Entity,
+    /// Global-space bounds of the part of the terrain the mouth overlapped.
+    pub(crate) region: Rect,
+    /// Global-space area dug out, including any that fell away as debris.
+    pub(crate) removed_area: f32,
+    pub(crate) carver: Entity,
+}
+
+/// Carves the terrain near every digging worm's mouth, one task per terrain entity, then writes
+/// the results back: outlines in place, so systems after this one see this frame's tunnels, and
+/// despawned terrain and new debris through `commands`.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn update_terrain(
     mut commands: Commands,
-    mut player_query: Query<(&Controls, &Transform), With<Player>>,
-    terrain_query: Query<(Entity, &Polygon, &Transform)>,
+    control_scheme: Res<ControlScheme>,
+    mut player_query: Query<(Entity, &Controls, Option<&InputBinding>, &Mouth, &Transform, &mut LastCarve), With<Player>>,
+    terrain_index: Res<TerrainIndex>,
+    tuning: Res<Tuning>,
+    bounds: Res<LevelBounds>,
+    mut terrain_query: Query<(&mut Polygon, &Transform, Option<&TerrainMaterial>, Has<Debris>), Without<Contour>>,
+    contour_query: Query<(&Polygon, &Transform), With<Contour>>,
+    mut chunk_query: Query<(Entity, &mut DensityChunk, &Transform)>,
+    mut tile_query: Query<(Entity, &mut BitmapTile, &Transform)>,
+    mut images: ResMut<Assets<Image>>,
+    mut carved_events: EventWriter<TerrainCarved>,
     mut gizmos: Gizmos,
 ) {
-    let (player_controls, player_transform) = player_query.single_mut();
+    let mut cuts = vec![];
+    for (player, player_controls, binding, player_mouth, player_transform, mut last_carve) in player_query.iter_mut() {
+        if !control_scheme.for_binding(binding).is_digging(player_controls) {
+            last_carve.reset();
+            continue;
+        }
 
-    if player_controls.action {
-        let mouth_polygon = Polygon::from(vec![
-            Vec2::new(2., 2.),
-            Vec2::new(6., 2.),
-            Vec2::new(6., -2.),
-            Vec2::new(2., -2.),
-        ]);
-        let mouth_bundle = PolygonTransformBundle::from((mouth_polygon.clone(), *player_transform));
+        let mouth_polygon = player_mouth.polygon()
+            .to_global_space(&Transform::from_translation(tuning.mouth_offset.extend(0.)));
+        let global_mouth_polygon = mouth_polygon.to_global_space(player_transform);
+        for position in global_mouth_polygon.vertices.iter() {
+            gizmos.circle_2d(*position, 0.25, Color::YELLOW);
+        }
+        if !last_carve.update(&global_mouth_polygon) {
+            continue;
+        }
+        cuts.push(Cut {
+            carver: player,
+            mouth: PolygonTransformBundle::from((mouth_polygon, *player_transform)),
+            aabb: global_mouth_polygon.aabb(),
+        });
+    }
+    if cuts.is_empty() {
+        return;
+    }
+    carve_density(&cuts, &mut chunk_query, &mut carved_events);
+    carve_bitmap(&cuts, &mut tile_query, &mut images, &mut carved_events);
 
-        for position in mouth_polygon.to_global_space(player_transform).vertices {
-            gizmos.circle_2d(position, 0.25, Color::YELLOW);
+    let mut nearby: Vec<Entity> = cuts.iter()
+        .flat_map(|cut| terrain_index.query(cut.aabb))
+        .collect();
+    nearby.sort();
+    nearby.dedup();
+    let terrain: Vec<(Entity, PolygonTransformBundle)> = nearby.into_iter()
+        .filter_map(|entity| terrain_query.get(entity).ok().map(|(polygon, transform, _, _)| (entity, polygon, transform)))
+        .filter(|(_, polygon, transform)| {
+            let terrain_aabb = polygon.to_global_space(transform).aabb();
+            cuts.iter().any(|cut| overlaps(cut.aabb, terrain_aabb))
+        })
+        .map(|(entity, polygon, transform)| (entity, PolygonTransformBundle::from((polygon.clone(), *transform))))
+        .collect();
+
+    for carve in carve_parallel(terrain, &cuts, &tuning.precision) {
+        for (index, region, removed_area) in carve.removed {
+            carved_events.send(TerrainCarved { entity: carve.entity, region, removed_area, carver: cuts[index].carver });
         }
+        // Untouched terrain is left alone so change detection only fires for geometry that changed.
+        let Some(pieces) = carve.pieces else {
+            continue;
+        };
 
-        for (entity, polygon, transform) in terrain_query.iter() {
-            let new_bundle = PolygonTransformBundle::from((polygon.clone(), transform.clone())).sink(&mouth_bundle);
-            let mut entity = commands.entity(entity);
-            entity.remove::<Polygon>();
-            entity.insert(new_bundle.polygon);
+        if pieces.is_empty() {
+            commands.entity(carve.entity).despawn();
+            continue;
+        }
+        let Ok((_, _, material, debris)) = terrain_query.get(carve.entity) else {
+            continue;
+        };
+        let material = material.copied().unwrap_or_default();
+
+        // Pieces held up by the level's edge or other terrain stay put and the rest fall. Debris
+        // keeps falling as its largest piece, and terrain nothing holds up keeps its largest piece
+        // hanging where the level placed it.
+        let supported: Vec<bool> = pieces.iter()
+            .map(|piece| {
+                if debris {
+                    return false;
+                }
+                let global_polygon = piece.polygon.to_global_space(&piece.transform);
+                let aabb = global_polygon.aabb();
+                let nearby = Rect::from_center_size(aabb.center(), aabb.size() + 2. * SUPPORT_DISTANCE);
+                let others: Vec<Polygon> = terrain_index.query(nearby).into_iter()
+                    .filter(|entity| *entity != carve.entity)
+                    .filter_map(|entity| match terrain_query.get(entity) {
+                        Ok((polygon, transform, _, false)) => Some(polygon.to_global_space(transform)),
+                        Ok(_) => None,
+                        Err(_) => contour_query.get(entity).ok().map(|(polygon, transform)| polygon.to_global_space(transform)),
+                    })
+                    .collect();
+                return is_supported(&global_polygon, bounds.0, &others);
+            })
+            .collect();
+        let anchor = supported.iter().position(|supported| *supported).unwrap_or(0);
+
+        for (index, piece) in pieces.into_iter().enumerate() {
+            if index == anchor {
+                if let Ok((mut polygon, _, _, _)) = terrain_query.get_mut(carve.entity) {
+                    *polygon = piece.polygon;
+                }
+            } else if supported[index] {
+                spawn_terrain(&mut commands, piece.polygon, piece.transform, material);
+            } else {
+                spawn_debris(&mut commands, piece);
+            }
         }
     }
 }
 
+/// Outlines terrain in its material's color. In the editor, also draws vertex handles and
+/// highlights the selection.
 fn update_terrain_gizmo(
-    terrain_query: Query<(&Polygon, &Transform)>,
+    editor: Res<Editor>,
+    terrain_query: Query<(Entity, &Polygon, &Transform, Option<&TerrainMaterial>)>,
     mut gizmos: Gizmos,
 ) {
-    for (polygon, transform) in terrain_query.iter() {
+    for (entity, polygon, transform, material) in terrain_query.iter() {
+        let selected = editor.enabled && editor.selected == Some(entity);
+        let color = if selected { Color::WHITE } else { material.copied().unwrap_or_default().color() };
         let vertices = polygon.to_global_space(transform).vertices;
         for index in 0..vertices.len() {
             gizmos.line(
                 vertices[index].extend(0.),
                 vertices[(index + 1) % vertices.len()].extend(0.),
-                Color::ORANGE,
+                color,
             );
+            if editor.enabled {
+                let handle_color = if selected && editor.vertex == Some(index) { Color::YELLOW } else { color };
+                gizmos.circle_2d(vertices[index], PICK_RADIUS / 2., handle_color);
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::LocalPlayers;
+
+    #[test]
+    fn test_local_players_from_args() {
+        let args = ["game", "--players", "3"].map(String::from);
+
+        assert_eq!(LocalPlayers::from_args(args.into_iter()), LocalPlayers(3));
+        assert_eq!(LocalPlayers::from_args(["game"].map(String::from).into_iter()), LocalPlayers(1));
+        assert_eq!(LocalPlayers::from_args(["game", "--players", "0"].map(String::from).into_iter()), LocalPlayers(1));
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::Path;
+use bevy::log::warn;
+use bevy::math::Vec2;
+use bevy::prelude::{Component, Transform};
+use serde::{Deserialize, Serialize};
+use crate::polygon::{Polygon, Shape};
+
+pub(crate) const MOUTH_PATH: &str = "assets/mouth.ron";
+
+/// Largest `size` upgrades can grow a mouth to, per axis.
+const MAX_MOUTH_SIZE: f32 = 16.;
+
+/// The region in front of a worm that carving removes.
+#[derive(Clone, Component, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct Mouth {
+    /// Outline around the mouth's own origin, scaled by `size`. A unit shape keeps `size` in
+    /// world units.
+    pub(crate) shape: Shape,
+    /// Position of the mouth's origin in the worm's local space.
+    pub(crate) offset: Vec2,
+    pub(crate) size: Vec2,
+}
+
+impl Default for Mouth {
+    fn default() -> Self {
+        Mouth {
+            shape: Shape::Rectangle(Vec2::ONE),
+            offset: Vec2::new(4., 0.),
+            size: Vec2::new(4., 4.),
+        }
+    }
+}
+
+/// The global-space mouth a worm last carved with, so a worm digging in place doesn't carve the
+/// same hole again every frame.
+#[derive(Component, Default)]
+pub(crate) struct LastCarve(Option<Polygon>);
+
+impl LastCarve {
+    /// Whether carving with `mouth` could change anything: it moved since the last carve, or this is
+    /// the first carve since digging started.
+    pub(crate) fn update(&mut self, mouth: &Polygon) -> bool {
+        if self.0.as_ref() == Some(mouth) {
+            return false;
+        }
+        self.0 = Some(mouth.clone());
+        return true;
+    }
+
+    /// Forgets the last carve once the worm stops digging.
+    pub(crate) fn reset(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Mouth {
+    /// Outline in the worm's local space.
+    pub(crate) fn polygon(&self) -> Polygon {
+        return self.shape.polygon().to_global_space(&Transform::from_translation(self.offset.extend(0.))
+            .with_scale(self.size.extend(1.)));
+    }
+
+    /// Grows the mouth by `factor`, keeping its back edge where it was so it stays clear of the
+    /// worm's body.
+    pub(crate) fn upgrade(&mut self, factor: f32) {
+        let size = (self.size * factor).min(Vec2::splat(MAX_MOUTH_SIZE));
+        self.offset.x += (size.x - self.size.x) / 2.;
+        self.size = size;
+    }
+
+    /// Reads a mouth definition, falling back to the default shape if it is missing or invalid.
+    pub(crate) fn load_or_default(path: impl AsRef<Path>) -> Mouth {
+        let path = path.as_ref();
+        return match fs::read_to_string(path).map(|source| ron::from_str(&source)) {
+            Ok(Ok(mouth)) => mouth,
+            Ok(Err(error)) => {
+                warn!("could not parse {}: {error}", path.display());
+                Mouth::default()
+            }
+            Err(error) => {
+                warn!("could not read {}: {error}", path.display());
+                Mouth::default()
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+    use crate::mouth::{LastCarve, MOUTH_PATH, Mouth};
+    use crate::polygon::Polygon;
+
+    #[test]
+    fn test_default_polygon() {
+        let expected = Polygon::from(vec![
+            Vec2::new(2., 2.),
+            Vec2::new(6., 2.),
+            Vec2::new(6., -2.),
+            Vec2::new(2., -2.),
+        ]);
+
+        assert_eq!(Mouth::default().polygon(), expected);
+    }
+
+    #[test]
+    fn test_upgrade_keeps_back_edge() {
+        let mut mouth = Mouth::default();
+
+        mouth.upgrade(1.5);
+
+        assert_eq!(mouth.size, Vec2::new(6., 6.));
+        assert_eq!(mouth.polygon().aabb().min, Vec2::new(2., -3.));
+    }
+
+    #[test]
+    fn test_upgrade_limit() {
+        let mut mouth = Mouth::default();
+
+        for _ in 0..32 {
+            mouth.upgrade(2.);
+        }
+
+        assert_eq!(mouth.size, Vec2::new(16., 16.));
+        assert_eq!(mouth.polygon().aabb().min.x, 2.);
+    }
+
+    #[test]
+    fn test_load_asset() {
+        assert_eq!(Mouth::load_or_default(MOUTH_PATH), Mouth::default());
+    }
+
+    #[test]
+    fn test_last_carve() {
+        let mut last_carve = LastCarve::default();
+        let mouth = Mouth::default().polygon();
+        let moved = Mouth { offset: Vec2::new(5., 0.), ..Mouth::default() }.polygon();
+
+        assert!(last_carve.update(&mouth));
+        assert!(!last_carve.update(&mouth));
+        assert!(last_carve.update(&moved));
+        last_carve.reset();
+        assert!(last_carve.update(&moved));
+    }
+}
@@ -0,0 +1,357 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use bevy::math::{Rect, Vec2};
+use bevy::prelude::{Changed, Commands, DetectChanges, Entity, EventReader, Local, Query, RemovedComponents, Res, ResMut, Resource, Transform, With};
+use bevy_rapier2d::dynamics::Sleeping;
+use crate::camera::LevelBounds;
+use crate::debris::Debris;
+use crate::polygon::Polygon;
+use crate::spatial::TerrainIndex;
+use crate::TerrainCarved;
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+const NEIGHBORS: [(isize, isize); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// Navigation graph over the negative space of the terrain: a grid of cells covering the level
+/// where each cell whose center lies outside every terrain `Polygon` is a node, connected to its
+/// open neighbors.
+#[derive(Clone, Debug, Resource)]
+pub(crate) struct NavGrid {
+    origin: Vec2,
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    open: Vec<bool>,
+}
+
+impl NavGrid {
+    pub(crate) fn new(bounds: Rect, cell_size: f32) -> Self {
+        let width = (bounds.width() / cell_size).ceil() as usize;
+        let height = (bounds.height() / cell_size).ceil() as usize;
+        NavGrid {
+            origin: bounds.min,
+            cell_size,
+            width,
+            height,
+            open: vec![true; width * height],
+        }
+    }
+
+    /// Closes every cell whose center is inside one of `solids`, given in global space.
+    pub(crate) fn rasterize<'a>(&mut self, solids: impl IntoIterator<Item = &'a Polygon>) {
+        self.rasterize_region(self.bounds(), solids);
+    }
+
+    /// Like `rasterize`, but only redoes the cells overlapping `region`, leaving the rest as they
+    /// were. `solids` needs to include everything overlapping those cells, all in global space.
+    pub(crate) fn rasterize_region<'a>(&mut self, region: Rect, solids: impl IntoIterator<Item = &'a Polygon>) {
+        let (min_x, min_y) = self.clamped_cell(region.min);
+        let (max_x, max_y) = self.clamped_cell(region.max);
+        for y in min_y..=max_y {
+            self.open[y * self.width + min_x..=y * self.width + max_x].fill(true);
+        }
+        for solid in solids {
+            let aabb = solid.aabb();
+            let (solid_min_x, solid_min_y) = self.clamped_cell(aabb.min);
+            let (solid_max_x, solid_max_y) = self.clamped_cell(aabb.max);
+            for y in solid_min_y.max(min_y)..=solid_max_y.min(max_y) {
+                for x in solid_min_x.max(min_x)..=solid_max_x.min(max_x) {
+                    if solid.contains(self.center((x, y))) {
+                        self.open[y * self.width + x] = false;
+                    }
+                }
+            }
+        }
+    }
+
+    fn bounds(&self) -> Rect {
+        let size = Vec2::new(self.width as f32, self.height as f32) * self.cell_size;
+        return Rect::from_corners(self.origin, self.origin + size);
+    }
+
+    pub(crate) fn size(&self) -> (usize, usize) {
+        return (self.width, self.height);
+    }
+
+    pub(crate) fn cell_size(&self) -> f32 {
+        return self.cell_size;
+    }
+
+    pub(crate) fn cell(&self, point: Vec2) -> Option<(usize, usize)> {
+        let cell = ((point - self.origin) / self.cell_size).floor();
+        if cell.x < 0. || cell.y < 0. || cell.x >= self.width as f32 || cell.y >= self.height as f32 {
+            return None;
+        }
+        return Some((cell.x as usize, cell.y as usize));
+    }
+
+    fn clamped_cell(&self, point: Vec2) -> (usize, usize) {
+        let cell = ((point - self.origin) / self.cell_size).floor();
+        return (
+            cell.x.clamp(0., self.width as f32 - 1.) as usize,
+            cell.y.clamp(0., self.height as f32 - 1.) as usize,
+        );
+    }
+
+    pub(crate) fn center(&self, (x, y): (usize, usize)) -> Vec2 {
+        return self.origin + (Vec2::new(x as f32, y as f32) + 0.5) * self.cell_size;
+    }
+
+    pub(crate) fn is_open(&self, (x, y): (usize, usize)) -> bool {
+        return self.open[y * self.width + x];
+    }
+
+    /// Open cells reachable in one step from `cell`, with their move cost. Diagonal moves may not
+    /// cut the corner of a closed cell.
+    fn neighbors(&self, (x, y): (usize, usize)) -> impl Iterator<Item = ((usize, usize), u32)> + '_ {
+        return NEIGHBORS.iter().filter_map(move |&(dx, dy)| {
+            let neighbor = self.offset((x, y), dx, dy)?;
+            if !self.is_open(neighbor) {
+                return None;
+            }
+            if dx != 0 && dy != 0 {
+                let corners_open = self.offset((x, y), dx, 0).is_some_and(|corner| self.is_open(corner))
+                    && self.offset((x, y), 0, dy).is_some_and(|corner| self.is_open(corner));
+                if !corners_open {
+                    return None;
+                }
+                return Some((neighbor, DIAGONAL_COST));
+            }
+            return Some((neighbor, STRAIGHT_COST));
+        });
+    }
+
+    fn offset(&self, (x, y): (usize, usize), dx: isize, dy: isize) -> Option<(usize, usize)> {
+        let x = x.checked_add_signed(dx).filter(|&x| x < self.width)?;
+        let y = y.checked_add_signed(dy).filter(|&y| y < self.height)?;
+        return Some((x, y));
+    }
+
+    fn index(&self, (x, y): (usize, usize)) -> usize {
+        return y * self.width + x;
+    }
+
+    /// A* over open cells from `from` to `to`, returning the cell centers to visit after `from`.
+    pub(crate) fn path(&self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        let start = self.cell(from)?;
+        let goal = self.cell(to).filter(|&goal| self.is_open(goal))?;
+
+        let heuristic = |(x, y): (usize, usize)| {
+            let dx = x.abs_diff(goal.0) as u32;
+            let dy = y.abs_diff(goal.1) as u32;
+            return STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy);
+        };
+
+        let mut came_from = vec![None; self.open.len()];
+        let mut cost = vec![u32::MAX; self.open.len()];
+        let mut frontier = BinaryHeap::new();
+        cost[self.index(start)] = 0;
+        frontier.push(Reverse((heuristic(start), start)));
+
+        while let Some(Reverse((_, current))) = frontier.pop() {
+            if current == goal {
+                return Some(self.reconstruct(&came_from, start, goal));
+            }
+            for (neighbor, step) in self.neighbors(current) {
+                let new_cost = cost[self.index(current)] + step;
+                if new_cost < cost[self.index(neighbor)] {
+                    cost[self.index(neighbor)] = new_cost;
+                    came_from[self.index(neighbor)] = Some(current);
+                    frontier.push(Reverse((new_cost + heuristic(neighbor), neighbor)));
+                }
+            }
+        }
+        return None;
+    }
+
+    /// Breadth-first search up to `steps` cells from `from` for the reachable cell farthest from
+    /// `threat`, returning the cell centers to visit after `from`.
+    pub(crate) fn flee(&self, from: Vec2, threat: Vec2, steps: usize) -> Option<Vec<Vec2>> {
+        let start = self.cell(from)?;
+
+        let mut came_from = vec![None; self.open.len()];
+        let mut depth = vec![usize::MAX; self.open.len()];
+        let mut frontier = VecDeque::from([start]);
+        let mut best = start;
+        depth[self.index(start)] = 0;
+
+        while let Some(current) = frontier.pop_front() {
+            if self.center(current).distance_squared(threat) > self.center(best).distance_squared(threat) {
+                best = current;
+            }
+            if depth[self.index(current)] == steps {
+                continue;
+            }
+            for (neighbor, _) in self.neighbors(current) {
+                if depth[self.index(neighbor)] == usize::MAX {
+                    depth[self.index(neighbor)] = depth[self.index(current)] + 1;
+                    came_from[self.index(neighbor)] = Some(current);
+                    frontier.push_back(neighbor);
+                }
+            }
+        }
+        return Some(self.reconstruct(&came_from, start, best));
+    }
+
+    fn reconstruct(&self, came_from: &[Option<(usize, usize)>], start: (usize, usize), goal: (usize, usize)) -> Vec<Vec2> {
+        let mut path = vec![];
+        let mut current = goal;
+        while current != start {
+            path.push(self.center(current));
+            current = came_from[self.index(current)].unwrap();
+        }
+        path.reverse();
+        return path;
+    }
+}
+
+pub(crate) fn startup_nav_grid(mut commands: Commands, bounds: Res<LevelBounds>) {
+    commands.insert_resource(NavGrid::new(bounds.0, 1.));
+}
+
+/// Rasterizes the grid again where terrain changed: the region of each carve, and wherever other
+/// terrain was spawned, edited or despawned, or debris that's still awake moved. A new grid, such
+/// as one sized for a level just loaded, is rasterized in full.
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_nav_grid(
+    mut nav_grid: ResMut<NavGrid>,
+    mut rasterized: Local<TerrainIndex>,
+    mut carved_events: EventReader<TerrainCarved>,
+    changed_query: Query<Entity, Changed<Polygon>>,
+    moved_query: Query<(Entity, &Sleeping), (With<Debris>, Changed<Transform>)>,
+    mut removed: RemovedComponents<Polygon>,
+    terrain_query: Query<(Entity, &Polygon, &Transform)>,
+) {
+    if nav_grid.is_changed() {
+        carved_events.clear();
+        removed.clear();
+        *rasterized = TerrainIndex::default();
+        let solids: Vec<Polygon> = terrain_query.iter()
+            .map(|(entity, polygon, transform)| {
+                let solid = polygon.to_global_space(transform);
+                rasterized.insert(entity, solid.aabb());
+                return solid;
+            })
+            .collect();
+        nav_grid.rasterize(&solids);
+        return;
+    }
+
+    let mut dirty = vec![];
+    for entity in removed.read() {
+        dirty.extend(rasterized.aabb(entity));
+        rasterized.remove(entity);
+    }
+    let carved: Vec<(Entity, Rect)> = carved_events.read()
+        .map(|carved_event| (carved_event.entity, carved_event.region))
+        .collect();
+    let mut entities: Vec<Entity> = changed_query.iter()
+        .chain(moved_query.iter().filter(|(_, sleeping)| !sleeping.sleeping).map(|(entity, _)| entity))
+        .collect();
+    entities.sort();
+    entities.dedup();
+    for entity in entities {
+        let Ok((_, polygon, transform)) = terrain_query.get(entity) else {
+            continue;
+        };
+        let aabb = polygon.to_global_space(transform).aabb();
+        // A carve only takes terrain away inside its region. Anything else may have left
+        // anywhere it was.
+        let regions: Vec<Rect> = carved.iter()
+            .filter(|(carved_entity, _)| *carved_entity == entity)
+            .map(|(_, region)| *region)
+            .collect();
+        if regions.is_empty() {
+            dirty.extend(rasterized.aabb(entity));
+            dirty.push(aabb);
+        } else {
+            dirty.extend(regions);
+        }
+        rasterized.insert(entity, aabb);
+    }
+
+    for region in dirty {
+        // Cells partly in the region are redone whole, so take in solids reaching into them.
+        let reach = Rect::from_center_size(region.center(), region.size() + 2. * nav_grid.cell_size());
+        let solids: Vec<Polygon> = rasterized.query(reach).into_iter()
+            .filter_map(|entity| terrain_query.get(entity).ok())
+            .map(|(_, polygon, transform)| polygon.to_global_space(transform))
+            .collect();
+        nav_grid.rasterize_region(region, &solids);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Rect, Vec2};
+    use crate::navigation::NavGrid;
+    use crate::polygon::Polygon;
+
+    fn wall(x: f32, min_y: f32, max_y: f32) -> Polygon {
+        return Polygon::from(vec![
+            Vec2::new(x - 0.5, min_y),
+            Vec2::new(x - 0.5, max_y),
+            Vec2::new(x + 0.5, max_y),
+            Vec2::new(x + 0.5, min_y),
+        ]);
+    }
+
+    #[test]
+    fn test_rasterize() {
+        let mut grid = NavGrid::new(Rect::new(0., 0., 8., 8.), 1.);
+
+        grid.rasterize(&[wall(4.5, 0., 6.)]);
+
+        assert!(!grid.is_open((4, 0)));
+        assert!(!grid.is_open((4, 5)));
+        assert!(grid.is_open((3, 0)));
+        assert!(grid.is_open((5, 0)));
+        assert!(grid.is_open((4, 6)));
+    }
+
+    #[test]
+    fn test_rasterize_region() {
+        let mut grid = NavGrid::new(Rect::new(0., 0., 8., 8.), 1.);
+        grid.rasterize(&[wall(4.5, 0., 6.), wall(1.5, 0., 8.)]);
+
+        grid.rasterize_region(Rect::new(4., 2., 5., 4.), &[wall(4.5, 0., 3.)]);
+
+        assert!(!grid.is_open((4, 2)));
+        assert!(grid.is_open((4, 3)));
+        assert!(!grid.is_open((4, 5)));
+        assert!(!grid.is_open((1, 3)));
+    }
+
+    #[test]
+    fn test_path_around_wall() {
+        let mut grid = NavGrid::new(Rect::new(0., 0., 8., 8.), 1.);
+        grid.rasterize(&[wall(4.5, 0., 6.)]);
+
+        let path = grid.path(Vec2::new(1.5, 0.5), Vec2::new(6.5, 0.5)).unwrap();
+
+        assert_eq!(path.last(), Some(&Vec2::new(6.5, 0.5)));
+        assert!(path.iter().all(|point| grid.is_open(grid.cell(*point).unwrap())));
+        assert!(path.iter().any(|point| point.y > 6.));
+    }
+
+    #[test]
+    fn test_path_blocked() {
+        let mut grid = NavGrid::new(Rect::new(0., 0., 8., 8.), 1.);
+        grid.rasterize(&[wall(4.5, -1., 9.)]);
+
+        assert_eq!(grid.path(Vec2::new(1.5, 0.5), Vec2::new(6.5, 0.5)), None);
+    }
+
+    #[test]
+    fn test_flee() {
+        let grid = NavGrid::new(Rect::new(0., 0., 8., 8.), 1.);
+        let from = Vec2::new(4.5, 4.5);
+        let threat = Vec2::new(2.5, 4.5);
+
+        let path = grid.flee(from, threat, 3).unwrap();
+
+        assert_eq!(path.len(), 3);
+        assert!(path.last().unwrap().distance(threat) > from.distance(threat) + 2.);
+    }
+}
@@ -0,0 +1,758 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use bevy::log::{info, warn};
+use bevy::math::Vec2;
+use bevy::asset::Assets;
+use bevy::prelude::{Commands, Component, DetectChanges, Entity, Has, Image, Or, Query, Ref, RemovedComponents, Res, ResMut, Resource, Time, Transform, TransformBundle, With, Without};
+use bevy_rapier2d::dynamics::RigidBody;
+use bevy_rapier2d::prelude::Velocity;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::bitmap::{BitmapTile, Mask, spawn_bitmap_tile};
+use crate::contour::Contour;
+use crate::controls::{ControlScheme, Controls};
+use crate::debris::{Debris, spawn_debris};
+use crate::density::{DensityChunk, spawn_density_chunk};
+use crate::level::TerrainMaterial;
+use crate::mouth::{MOUTH_PATH, Mouth};
+use crate::polygon::Polygon;
+use crate::polygon_transform_bundle::PolygonTransformBundle;
+use crate::tuning::Tuning;
+use crate::{LocalPlayers, MAX_HEALTH, Player, spawn_terrain, spawn_worm};
+
+/// Largest payload of a single UDP datagram.
+const MAX_DATAGRAM: usize = 65507;
+/// Bytes of an encoded message carried per datagram, small enough to avoid IP fragmentation.
+/// Longer messages are split across several datagrams and put back together on arrival.
+const FRAGMENT_SIZE: usize = 1200;
+/// Message number, fragment index and fragment count at the start of every datagram.
+const HEADER_SIZE: usize = 8;
+/// Longest encoded message sent or put back together, which bounds what a fragment header can
+/// make the receiver set aside.
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+/// Messages per sender still being put back together. Older ones have lost a fragment for good.
+const MAX_PARTIAL_MESSAGES: u32 = 64;
+/// Time after which a message still missing fragments is given up on, whoever sent it.
+const PARTIAL_TIMEOUT: Duration = Duration::from_secs(2);
+/// Terrain entities resent every frame whether they changed or not, cycling through all of them
+/// so that deltas lost in transit are eventually repaired.
+const REFRESH_PER_SEND: usize = 4;
+/// Seconds without input after which a client is dropped and its worm despawned.
+const CLIENT_TIMEOUT: f32 = 5.;
+/// Seconds between lists of every live terrain id, which let clients drop terrain whose removal
+/// they never heard about.
+const MANIFEST_INTERVAL: f32 = 1.;
+/// Distance between the server's and the predicted worm position beyond which the client snaps
+/// to the server.
+const RECONCILE_DISTANCE: f32 = 2.;
+/// Predicted positions kept for inputs the server hasn't acknowledged yet.
+const MAX_HISTORY: usize = 256;
+
+/// Parsed from `--server <address>` or `--connect <address>` on the command line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum NetRole {
+    /// Owns the terrain, carves it for every worm and sends clients what changed.
+    Server(SocketAddr),
+    /// Predicts its own worm and shows the terrain the server sends.
+    Client(SocketAddr),
+}
+
+impl NetRole {
+    pub(crate) fn from_args(mut args: impl Iterator<Item = String>) -> Option<NetRole> {
+        while let Some(arg) = args.next() {
+            let role: fn(SocketAddr) -> NetRole = match arg.as_str() {
+                "--server" => NetRole::Server,
+                "--connect" => NetRole::Client,
+                _ => continue,
+            };
+            return match args.next().map(|address| address.parse()) {
+                Some(Ok(address)) => Some(role(address)),
+                _ => {
+                    warn!("{arg} needs an address such as 127.0.0.1:7777");
+                    None
+                }
+            };
+        }
+        return None;
+    }
+}
+
+/// Identifies a terrain entity across the server and its clients. Terrain is sent a whole entity
+/// at a time, so each entity is one chunk of the delta.
+#[derive(Clone, Copy, Component, Debug, PartialEq)]
+pub(crate) struct TerrainId(pub(crate) u32);
+
+/// Another player's worm as a client sees it, placed wherever the server last said it was.
+#[derive(Component)]
+pub(crate) struct ReplicatedWorm(u64);
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) enum ClientMessage {
+    /// The client's controls for its input number `sequence`.
+    Input { sequence: u32, controls: Controls },
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) enum ServerMessage {
+    Terrain { id: u32, polygon: Polygon, transform: Transform, debris: bool, material: TerrainMaterial },
+    /// A density chunk, whose contours the client traces for itself.
+    Chunk { id: u32, chunk: DensityChunk, transform: Transform, material: TerrainMaterial },
+    /// A bitmap tile's pixels, apron included, whose contours the client traces for itself.
+    Tile { id: u32, mask: Mask, transform: Transform, material: TerrainMaterial },
+    TerrainRemoved { id: u32 },
+    /// Where debris whose outline hasn't changed has fallen to, and how it's moving.
+    DebrisMoved { id: u32, transform: Transform, linvel: Vec2, angvel: f32 },
+    /// Every terrain id still in use. Ids below `next_id` that aren't listed have been removed.
+    TerrainManifest { ids: Vec<u32>, next_id: u32 },
+    /// The receiving client's worm after the server applied its input number `sequence`.
+    Worm { sequence: u32, transform: Transform, linvel: Vec2 },
+    /// Every other worm in the game, by an id that stays the same while the worm exists.
+    OtherWorms { worms: Vec<(u64, Transform)> },
+}
+
+/// Non-blocking UDP socket exchanging CBOR-encoded messages, each split into as many datagrams as
+/// it takes.
+pub(crate) struct NetSocket {
+    socket: UdpSocket,
+    next_message: u32,
+    /// Fragments received so far of messages still incomplete, by sender and message number.
+    partial: HashMap<(SocketAddr, u32), PartialMessage>,
+}
+
+struct PartialMessage {
+    fragments: Vec<Option<Vec<u8>>>,
+    /// When the first fragment arrived.
+    started: Instant,
+}
+
+impl NetSocket {
+    pub(crate) fn bind(address: SocketAddr) -> io::Result<NetSocket> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        return Ok(NetSocket { socket, next_message: 0, partial: HashMap::new() });
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        return self.socket.local_addr();
+    }
+
+    pub(crate) fn send(&mut self, address: SocketAddr, message: &impl Serialize) {
+        let mut encoded = vec![];
+        if let Err(error) = ciborium::into_writer(message, &mut encoded) {
+            warn!("could not encode message to {address}: {error}");
+            return;
+        }
+        let number = self.next_message;
+        self.next_message = self.next_message.wrapping_add(1);
+
+        if encoded.len() > MAX_MESSAGE_SIZE {
+            warn!("could not send to {address}: message of {} bytes is too long", encoded.len());
+            return;
+        }
+        let count = encoded.len().div_ceil(FRAGMENT_SIZE).max(1);
+        for index in 0..count {
+            let payload = &encoded[(index * FRAGMENT_SIZE).min(encoded.len())..((index + 1) * FRAGMENT_SIZE).min(encoded.len())];
+            let mut datagram = Vec::with_capacity(HEADER_SIZE + payload.len());
+            datagram.extend_from_slice(&number.to_le_bytes());
+            datagram.extend_from_slice(&(index as u16).to_le_bytes());
+            datagram.extend_from_slice(&(count as u16).to_le_bytes());
+            datagram.extend_from_slice(payload);
+            if let Err(error) = self.socket.send_to(&datagram, address) {
+                warn!("could not send to {address}: {error}");
+                return;
+            }
+        }
+    }
+
+    /// Every complete message waiting on the socket. Datagrams and messages that fail to decode
+    /// are logged and dropped.
+    pub(crate) fn receive<T: DeserializeOwned>(&mut self) -> Vec<(SocketAddr, T)> {
+        let mut messages = vec![];
+        let mut buffer = vec![0; MAX_DATAGRAM];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((length, address)) => {
+                    let Some(encoded) = self.reassemble(address, &buffer[..length], Instant::now()) else {
+                        continue;
+                    };
+                    match ciborium::from_reader(encoded.as_slice()) {
+                        Ok(message) => messages.push((address, message)),
+                        Err(error) => warn!("could not decode message from {address}: {error}"),
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                // Windows reports an earlier send to a closed port on the next receive.
+                Err(error) if error.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(error) => {
+                    warn!("could not receive: {error}");
+                    break;
+                }
+            }
+        }
+        return messages;
+    }
+
+    /// Files away one datagram received at `now`, returning the message it completes, if any.
+    fn reassemble(&mut self, address: SocketAddr, datagram: &[u8], now: Instant) -> Option<Vec<u8>> {
+        if datagram.len() < HEADER_SIZE {
+            warn!("could not decode message from {address}: datagram too short");
+            return None;
+        }
+        let number = u32::from_le_bytes([datagram[0], datagram[1], datagram[2], datagram[3]]);
+        let index = u16::from_le_bytes([datagram[4], datagram[5]]) as usize;
+        let count = u16::from_le_bytes([datagram[6], datagram[7]]) as usize;
+        let payload = &datagram[HEADER_SIZE..];
+        if index >= count || count > MAX_MESSAGE_SIZE.div_ceil(FRAGMENT_SIZE) {
+            warn!("could not decode message from {address}: fragment {index} of {count}");
+            return None;
+        }
+        if count == 1 {
+            return Some(payload.to_vec());
+        }
+
+        // Forget messages that took too long, and those from this sender too far from this one to
+        // ever complete.
+        self.partial.retain(|(sender, partial_number), partial| {
+            let close = number.wrapping_sub(*partial_number).min(partial_number.wrapping_sub(number)) < MAX_PARTIAL_MESSAGES;
+            return now.duration_since(partial.started) < PARTIAL_TIMEOUT && (*sender != address || close);
+        });
+        let partial = self.partial.entry((address, number))
+            .or_insert_with(|| PartialMessage { fragments: vec![None; count], started: now });
+        if partial.fragments.len() != count {
+            return None;
+        }
+        partial.fragments[index] = Some(payload.to_vec());
+        if partial.fragments.iter().any(|fragment| fragment.is_none()) {
+            return None;
+        }
+        let partial = self.partial.remove(&(address, number))?;
+        return Some(partial.fragments.into_iter().flatten().flatten().collect());
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct NetServer {
+    socket: NetSocket,
+    clients: HashMap<SocketAddr, Entity>,
+    /// Ids handed out so far, by entity, to report which ones were removed.
+    terrain_ids: HashMap<Entity, u32>,
+    next_terrain_id: u32,
+    /// `Time::elapsed_seconds` when the last `TerrainManifest` went out.
+    last_manifest: f32,
+    /// Clients that joined so far, which number their worms after the local players' so no two
+    /// worms share a number even once some clients have left.
+    next_worm_index: usize,
+    /// Id the next unprompted terrain refresh starts from.
+    refresh_from: u32,
+}
+
+impl From<NetSocket> for NetServer {
+    fn from(socket: NetSocket) -> Self {
+        NetServer {
+            socket,
+            clients: HashMap::new(),
+            terrain_ids: HashMap::new(),
+            next_terrain_id: 0,
+            last_manifest: 0.,
+            next_worm_index: 0,
+            refresh_from: 0,
+        }
+    }
+}
+
+/// A worm played by a networked client, simulated on the server.
+#[derive(Component)]
+pub(crate) struct RemoteWorm {
+    address: SocketAddr,
+    /// Latest input applied.
+    sequence: u32,
+    /// `Time::elapsed_seconds` when the client was last heard from.
+    last_heard: f32,
+    /// Whether the client still needs all the terrain, as it does when it joins.
+    needs_snapshot: bool,
+}
+
+#[derive(Resource)]
+pub(crate) struct NetClient {
+    socket: NetSocket,
+    server: SocketAddr,
+    sequence: u32,
+    /// Predicted position of the local worm when each unacknowledged input was sent.
+    history: VecDeque<(u32, Vec2)>,
+}
+
+impl NetClient {
+    pub(crate) fn new(socket: NetSocket, server: SocketAddr) -> Self {
+        NetClient {
+            socket,
+            server,
+            sequence: 0,
+            history: VecDeque::new(),
+        }
+    }
+}
+
+pub(crate) fn is_client(client: Option<Res<NetClient>>) -> bool {
+    return client.is_some();
+}
+
+/// Correction to move the predicted worm by, given where the server put it after input
+/// `sequence`. Forgets acknowledged inputs and shifts the remaining predictions by the same
+/// correction. Small errors are left for the prediction to absorb.
+fn reconcile(history: &mut VecDeque<(u32, Vec2)>, sequence: u32, server_position: Vec2) -> Option<Vec2> {
+    while history.front().is_some_and(|(predicted_sequence, _)| *predicted_sequence < sequence) {
+        history.pop_front();
+    }
+    let (predicted_sequence, predicted_position) = history.pop_front()?;
+    if predicted_sequence != sequence {
+        history.push_front((predicted_sequence, predicted_position));
+        return None;
+    }
+
+    let error = server_position - predicted_position;
+    if error.length() <= RECONCILE_DISTANCE {
+        return None;
+    }
+    for (_, position) in history.iter_mut() {
+        *position += error;
+    }
+    return Some(error);
+}
+
+/// Ids among `known` that a `TerrainManifest` of `ids` and `next_id` says were removed. Ids from
+/// `next_id` on are newer than the manifest, which may have arrived late.
+fn stale_ids(ids: &[u32], next_id: u32, known: impl Iterator<Item = u32>) -> Vec<u32> {
+    let ids: HashSet<u32> = ids.iter().copied().collect();
+    return known.filter(|id| *id < next_id && !ids.contains(id)).collect();
+}
+
+/// Gives every terrain entity on the server an id to refer to it by. Contours are left out, since
+/// clients rebuild them from the chunks and tiles they come from.
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_terrain_ids(
+    mut commands: Commands,
+    mut server: ResMut<NetServer>,
+    terrain_query: Query<Entity, (Or<(With<Polygon>, With<DensityChunk>, With<BitmapTile>)>, Without<Contour>, Without<TerrainId>)>,
+) {
+    for entity in terrain_query.iter() {
+        let id = server.next_terrain_id;
+        server.next_terrain_id += 1;
+        server.terrain_ids.insert(entity, id);
+        commands.entity(entity).insert(TerrainId(id));
+    }
+}
+
+pub(crate) fn update_server_receive(
+    mut commands: Commands,
+    mut server: ResMut<NetServer>,
+    time: Res<Time>,
+    tuning: Res<Tuning>,
+    local_players: Res<LocalPlayers>,
+    mut worm_query: Query<(Entity, &mut Controls, &mut RemoteWorm)>,
+) {
+    let now = time.elapsed_seconds();
+    for (address, message) in server.socket.receive::<ClientMessage>() {
+        let ClientMessage::Input { sequence, controls } = message;
+        let Some(entity) = server.clients.get(&address).copied() else {
+            let index = local_players.0 + server.next_worm_index;
+            server.next_worm_index += 1;
+            let entity = spawn_worm(
+                &mut commands,
+                &tuning,
+                index,
+                Transform::from_xyz(-4., 4., 0.),
+                Velocity::default(),
+                Mouth::load_or_default(MOUTH_PATH),
+                MAX_HEALTH,
+            );
+            commands.entity(entity)
+                .insert(controls)
+                .insert(RemoteWorm { address, sequence, last_heard: now, needs_snapshot: true });
+            server.clients.insert(address, entity);
+            info!("{address} joined");
+            continue;
+        };
+
+        if let Ok((_, mut worm_controls, mut worm)) = worm_query.get_mut(entity) {
+            worm.last_heard = now;
+            if sequence > worm.sequence {
+                *worm_controls = controls;
+                worm.sequence = sequence;
+            }
+        }
+    }
+
+    for (entity, _, worm) in worm_query.iter() {
+        if now - worm.last_heard > CLIENT_TIMEOUT {
+            info!("{} timed out", worm.address);
+            server.clients.remove(&worm.address);
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Moves client worms on the server the way `update_player` moves local ones.
+pub(crate) fn update_remote_worms(
+    time: Res<Time>,
+    control_scheme: Res<ControlScheme>,
+    tuning: Res<Tuning>,
+    mut worm_query: Query<(&Controls, &mut Velocity, &mut Transform), With<RemoteWorm>>,
+) {
+    for (controls, mut velocity, mut transform) in worm_query.iter_mut() {
+        let motion = control_scheme.motion(controls, &transform, tuning.player_speed, tuning.player_turn_speed, time.delta_seconds());
+        velocity.linvel = motion.linvel;
+        transform.rotate_z(motion.rotation);
+    }
+}
+
+/// Sends clients the terrain that changed this frame, where debris moved, the next few entities in
+/// line for a refresh and all of it to clients that just joined, every so often the ids of all of
+/// it, then where every worm ended up.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn update_server_send(
+    mut server: ResMut<NetServer>,
+    time: Res<Time>,
+    terrain_query: Query<(Entity, Ref<Polygon>, Ref<Transform>, Option<&Velocity>, Has<Debris>), Without<Contour>>,
+    chunk_query: Query<(Entity, Ref<DensityChunk>, &Transform, &TerrainMaterial)>,
+    tile_query: Query<(Entity, Ref<BitmapTile>, &Transform, &TerrainMaterial)>,
+    material_query: Query<&TerrainMaterial>,
+    mut worm_query: Query<(Entity, &mut RemoteWorm, &Transform, &Velocity)>,
+    player_query: Query<(Entity, &Transform), With<Player>>,
+    mut removed_ids: RemovedComponents<TerrainId>,
+) {
+    let mut removed = vec![];
+    for entity in removed_ids.read() {
+        if let Some(id) = server.terrain_ids.remove(&entity) {
+            removed.push(ServerMessage::TerrainRemoved { id });
+        }
+    }
+
+    let mut terrain: Vec<(u32, bool, ServerMessage)> = terrain_query.iter()
+        .filter_map(|(entity, polygon, transform, _, debris)| {
+            let id = server.terrain_ids.get(&entity).copied()?;
+            return Some((id, polygon.is_changed(), ServerMessage::Terrain {
+                id,
+                polygon: polygon.clone(),
+                transform: *transform,
+                debris,
+                material: material_query.get(entity).copied().unwrap_or_default(),
+            }));
+        })
+        .collect();
+    terrain.extend(chunk_query.iter().filter_map(|(entity, chunk, transform, material)| {
+        let id = server.terrain_ids.get(&entity).copied()?;
+        return Some((id, chunk.is_changed(), ServerMessage::Chunk { id, chunk: chunk.clone(), transform: *transform, material: *material }));
+    }));
+    terrain.extend(tile_query.iter().filter_map(|(entity, tile, transform, material)| {
+        let id = server.terrain_ids.get(&entity).copied()?;
+        return Some((id, tile.is_changed(), ServerMessage::Tile { id, mask: tile.mask().clone(), transform: *transform, material: *material }));
+    }));
+    terrain.sort_by_key(|(id, _, _)| *id);
+
+    // Refresh the next few ids after the last refreshed, wrapping around to the start.
+    let start = terrain.iter().position(|(id, _, _)| *id >= server.refresh_from).unwrap_or(0);
+    let refreshed: Vec<usize> = (0..terrain.len().min(REFRESH_PER_SEND))
+        .map(|offset| (start + offset) % terrain.len())
+        .collect();
+    if let Some(last) = refreshed.last() {
+        server.refresh_from = terrain[*last].0 + 1;
+    }
+    let updates: Vec<&ServerMessage> = terrain.iter().enumerate()
+        .filter(|(index, (_, changed, _))| *changed || refreshed.contains(index))
+        .map(|(_, (_, _, message))| message)
+        .collect();
+    let moved: Vec<ServerMessage> = terrain_query.iter()
+        .filter(|(_, polygon, transform, _, debris)| *debris && transform.is_changed() && !polygon.is_changed())
+        .filter_map(|(entity, _, transform, velocity, _)| {
+            let id = server.terrain_ids.get(&entity).copied()?;
+            let velocity = velocity.copied().unwrap_or_default();
+            return Some(ServerMessage::DebrisMoved { id, transform: *transform, linvel: velocity.linvel, angvel: velocity.angvel });
+        })
+        .collect();
+    let manifest = (time.elapsed_seconds() - server.last_manifest >= MANIFEST_INTERVAL).then(|| {
+        server.last_manifest = time.elapsed_seconds();
+        ServerMessage::TerrainManifest { ids: server.terrain_ids.values().copied().collect(), next_id: server.next_terrain_id }
+    });
+
+    for (entity, mut worm, transform, velocity) in worm_query.iter_mut() {
+        let address = worm.address;
+        for message in removed.iter().chain(manifest.iter()) {
+            server.socket.send(address, message);
+        }
+        if worm.needs_snapshot {
+            for (_, _, message) in terrain.iter() {
+                server.socket.send(address, message);
+            }
+            worm.needs_snapshot = false;
+        } else {
+            for message in updates.iter() {
+                server.socket.send(address, *message);
+            }
+            for message in moved.iter() {
+                server.socket.send(address, message);
+            }
+        }
+        server.socket.send(address, &ServerMessage::Worm {
+            sequence: worm.sequence,
+            transform: *transform,
+            linvel: velocity.linvel,
+        });
+        let worms = player_query.iter()
+            .filter(|(other, _)| *other != entity)
+            .map(|(other, other_transform)| (other.to_bits(), *other_transform))
+            .collect();
+        server.socket.send(address, &ServerMessage::OtherWorms { worms });
+    }
+}
+
+/// Sends the local worm's controls to the server and remembers where it was predicted to be.
+pub(crate) fn update_client_send(
+    mut client: ResMut<NetClient>,
+    player_query: Query<(&Player, &Controls, &Transform)>,
+) {
+    let Some((_, controls, transform)) = player_query.iter().find(|(player, _, _)| player.0 == 0) else {
+        return;
+    };
+
+    client.sequence += 1;
+    let sequence = client.sequence;
+    client.history.push_back((sequence, transform.translation.truncate()));
+    if client.history.len() > MAX_HISTORY {
+        client.history.pop_front();
+    }
+    let server = client.server;
+    client.socket.send(server, &ClientMessage::Input { sequence, controls: controls.clone() });
+}
+
+/// Applies terrain and other worms from the server and corrects the predicted local worm.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn update_client_receive(
+    mut commands: Commands,
+    mut client: ResMut<NetClient>,
+    tuning: Res<Tuning>,
+    mut images: ResMut<Assets<Image>>,
+    terrain_query: Query<(Entity, &TerrainId)>,
+    mut tile_query: Query<&mut BitmapTile>,
+    mut player_query: Query<(&Player, &mut Transform, &mut Velocity), Without<TerrainId>>,
+    mut replicated_query: Query<(Entity, &ReplicatedWorm, &mut Transform), (Without<Player>, Without<TerrainId>)>,
+) {
+    let server = client.server;
+    let mut entities: HashMap<u32, Entity> = terrain_query.iter().map(|(entity, id)| (id.0, entity)).collect();
+    // Worms spawned this frame, which the query won't see until the commands are applied.
+    let mut spawned_worms = vec![];
+
+    for (address, message) in client.socket.receive::<ServerMessage>() {
+        if address != server {
+            continue;
+        }
+        match message {
+            ServerMessage::Terrain { id, polygon, transform, debris, material } => {
+                match entities.get(&id) {
+                    // Debris is respawned so that its collider is rebuilt for the new outline.
+                    Some(entity) if debris => commands.entity(*entity).despawn(),
+                    Some(entity) => {
+                        commands.entity(*entity).insert(polygon).insert(transform);
+                        continue;
+                    }
+                    None => {}
+                }
+                let entity = if debris {
+                    spawn_debris(&mut commands, PolygonTransformBundle::from((polygon, transform)))
+                } else {
+                    spawn_terrain(&mut commands, polygon, transform, material)
+                };
+                commands.entity(entity).insert(TerrainId(id));
+                entities.insert(id, entity);
+            }
+            ServerMessage::Chunk { id, chunk, transform, material } => {
+                if let Some(entity) = entities.get(&id) {
+                    commands.entity(*entity).insert(chunk).insert(transform);
+                    continue;
+                }
+                let entity = spawn_density_chunk(&mut commands, chunk, transform, material);
+                commands.entity(entity).insert(TerrainId(id));
+                entities.insert(id, entity);
+            }
+            ServerMessage::Tile { id, mask, transform, material } => {
+                if let Some(entity) = entities.get(&id) {
+                    // A tile spawned this frame isn't in the query yet. The refresh will resend it.
+                    if let Ok(mut tile) = tile_query.get_mut(*entity) {
+                        tile.set_mask(mask, material.color(), &mut images);
+                    }
+                    continue;
+                }
+                let entity = spawn_bitmap_tile(&mut commands, &mut images, mask, transform, material);
+                commands.entity(entity).insert(TerrainId(id));
+                entities.insert(id, entity);
+            }
+            ServerMessage::TerrainRemoved { id } => {
+                if let Some(entity) = entities.remove(&id) {
+                    commands.entity(entity).despawn();
+                }
+            }
+            ServerMessage::DebrisMoved { id, transform, linvel, angvel } => {
+                if let Some(entity) = entities.get(&id) {
+                    commands.entity(*entity).insert(transform).insert(Velocity { linvel, angvel });
+                }
+            }
+            ServerMessage::TerrainManifest { ids, next_id } => {
+                for id in stale_ids(&ids, next_id, entities.keys().copied()) {
+                    if let Some(entity) = entities.remove(&id) {
+                        commands.entity(entity).despawn();
+                    }
+                }
+            }
+            ServerMessage::Worm { sequence, transform, linvel } => {
+                let Some((_, mut player_transform, mut player_velocity)) = player_query.iter_mut().find(|(player, _, _)| player.0 == 0) else {
+                    continue;
+                };
+                if let Some(correction) = reconcile(&mut client.history, sequence, transform.translation.truncate()) {
+                    player_transform.translation += correction.extend(0.);
+                    player_velocity.linvel = linvel;
+                }
+            }
+            ServerMessage::OtherWorms { worms } => {
+                for (entity, replicated, mut transform) in replicated_query.iter_mut() {
+                    match worms.iter().find(|(id, _)| *id == replicated.0) {
+                        Some((_, worm_transform)) => *transform = *worm_transform,
+                        None => commands.entity(entity).despawn(),
+                    }
+                }
+                for (id, transform) in worms {
+                    if !spawned_worms.contains(&id) && replicated_query.iter().all(|(_, replicated, _)| replicated.0 != id) {
+                        spawned_worms.push(id);
+                        commands.spawn(RigidBody::KinematicPositionBased)
+                            .insert(TransformBundle::from_transform(transform))
+                            .insert(tuning.collider())
+                            .insert(ReplicatedWorm(id));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use bevy::math::Vec2;
+    use bevy::prelude::Transform;
+    use crate::bitmap::Mask;
+    use crate::level::TerrainMaterial;
+    use crate::net::{FRAGMENT_SIZE, MAX_MESSAGE_SIZE, NetRole, NetSocket, PARTIAL_TIMEOUT, ServerMessage, reconcile, stale_ids};
+    use crate::polygon::Polygon;
+
+    #[test]
+    fn test_role_from_args() {
+        let args = ["game", "--connect", "127.0.0.1:7777"].map(String::from);
+
+        assert_eq!(NetRole::from_args(args.into_iter()), Some(NetRole::Client("127.0.0.1:7777".parse().unwrap())));
+        assert_eq!(NetRole::from_args(["game"].map(String::from).into_iter()), None);
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let mut history = VecDeque::from([(1, Vec2::ZERO), (2, Vec2::new(1., 0.)), (3, Vec2::new(2., 0.))]);
+
+        assert_eq!(reconcile(&mut history, 1, Vec2::new(0.5, 0.)), None);
+        assert_eq!(reconcile(&mut history, 2, Vec2::new(1., 4.)), Some(Vec2::new(0., 4.)));
+        assert_eq!(history, VecDeque::from([(3, Vec2::new(2., 4.))]));
+    }
+
+    #[test]
+    fn test_stale_ids() {
+        let mut actual = stale_ids(&[1, 4], 6, [1, 2, 3, 4, 6, 7].into_iter());
+        actual.sort();
+
+        assert_eq!(actual, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_loopback() {
+        loopback(ServerMessage::Terrain {
+            id: 7,
+            polygon: Polygon::rectangle(Vec2::ONE),
+            transform: Transform::from_xyz(32., -32., 0.),
+            debris: false,
+            material: TerrainMaterial::Rock,
+        });
+    }
+
+    #[test]
+    fn test_loopback_fragmented() {
+        // Tens of kilobytes, far more than one datagram carries.
+        loopback(ServerMessage::Terrain {
+            id: 7,
+            polygon: Polygon::circle(0.5, 4096),
+            transform: Transform::from_xyz(32., -32., 0.),
+            debris: false,
+            material: TerrainMaterial::Rock,
+        });
+    }
+
+    #[test]
+    fn test_loopback_tile() {
+        loopback(ServerMessage::Tile {
+            id: 7,
+            mask: Mask { width: 66, height: 66, solid: (0..66 * 66).map(|index| index % 3 == 0).collect() },
+            transform: Transform::from_xyz(32., -32., 0.),
+            material: TerrainMaterial::Clay,
+        });
+    }
+
+    fn datagram(number: u32, index: u16, count: u16) -> Vec<u8> {
+        let mut datagram = vec![];
+        datagram.extend_from_slice(&number.to_le_bytes());
+        datagram.extend_from_slice(&index.to_le_bytes());
+        datagram.extend_from_slice(&count.to_le_bytes());
+        datagram.extend_from_slice(&[0; 16]);
+        return datagram;
+    }
+
+    #[test]
+    fn test_reassemble_rejects_oversized() {
+        let mut socket = NetSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let sender = "127.0.0.1:7777".parse().unwrap();
+        let count = MAX_MESSAGE_SIZE.div_ceil(FRAGMENT_SIZE) as u16;
+
+        assert_eq!(socket.reassemble(sender, &datagram(1, 0, count), Instant::now()), None);
+        assert_eq!(socket.partial.len(), 1);
+        assert_eq!(socket.reassemble(sender, &datagram(2, 0, count + 1), Instant::now()), None);
+        assert_eq!(socket.reassemble(sender, &datagram(3, 0, u16::MAX), Instant::now()), None);
+        assert_eq!(socket.partial.len(), 1);
+    }
+
+    #[test]
+    fn test_partial_messages_expire() {
+        let mut socket = NetSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let gone = "127.0.0.1:7777".parse().unwrap();
+        let other = "127.0.0.1:7778".parse().unwrap();
+        let now = Instant::now();
+
+        socket.reassemble(gone, &datagram(1, 0, 2), now);
+        socket.reassemble(other, &datagram(1, 0, 2), now + PARTIAL_TIMEOUT / 2);
+        socket.reassemble(other, &datagram(2, 0, 2), now + PARTIAL_TIMEOUT);
+
+        assert_eq!(socket.partial.keys().filter(|(sender, _)| *sender == gone).count(), 0);
+        assert_eq!(socket.partial.len(), 2);
+    }
+
+    fn loopback(expected: ServerMessage) {
+        let mut server = NetSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut client = NetSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        server.send(client.local_addr().unwrap(), &expected);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut actual = client.receive::<ServerMessage>();
+        while actual.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+            actual = client.receive::<ServerMessage>();
+        }
+
+        assert_eq!(actual, vec![(server.local_addr().unwrap(), expected)]);
+    }
+}
@@ -0,0 +1,223 @@
+use std::f32::consts::PI;
+use bevy::math::{Rect, Vec2};
+use bevy::prelude::{Commands, Component, Entity, EventReader, Query, Res, ResMut, Resource, Time, Transform, TransformBundle, Without};
+use bevy_rapier2d::dynamics::{LockedAxes, RigidBody, RigidBodyDisabled};
+use bevy_rapier2d::geometry::{Collider, ColliderDisabled};
+use bevy_rapier2d::prelude::Velocity;
+use crate::TerrainCarved;
+
+/// Angle between consecutive seeds of a sunflower spiral, which spreads points evenly over a disc.
+const GOLDEN_ANGLE: f32 = PI * 0.763_932;
+
+#[derive(Resource)]
+pub(crate) struct ParticleSettings {
+    /// Particles spawned per unit of global-space area dug out.
+    pub(crate) per_area: f32,
+    pub(crate) max_per_carve: usize,
+    /// Most particles simulated at once; carves beyond it spawn fewer or none.
+    pub(crate) budget: usize,
+    pub(crate) radius: f32,
+    pub(crate) speed: f32,
+    /// A particle slower than `settle_speed` for `settle_time` seconds has settled.
+    pub(crate) settle_speed: f32,
+    pub(crate) settle_time: f32,
+    pub(crate) lifetime: f32,
+}
+
+impl Default for ParticleSettings {
+    fn default() -> Self {
+        ParticleSettings {
+            per_area: 0.5,
+            max_per_carve: 8,
+            budget: 256,
+            radius: 0.25,
+            speed: 4.,
+            settle_speed: 0.1,
+            settle_time: 0.5,
+            lifetime: 4.,
+        }
+    }
+}
+
+/// Particles that settled or expired, kept disabled for reuse instead of despawned.
+#[derive(Default, Resource)]
+pub(crate) struct ParticlePool {
+    free: Vec<Entity>,
+    alive: usize,
+}
+
+#[derive(Component, Default)]
+pub(crate) struct Particle {
+    age: f32,
+    still: f32,
+}
+
+impl ParticleSettings {
+    fn count(&self, area: f32, alive: usize) -> usize {
+        let wanted = ((area * self.per_area).round() as usize).min(self.max_per_carve);
+        return wanted.min(self.budget.saturating_sub(alive));
+    }
+}
+
+/// Points spread over a disc of `radius` around `center`.
+fn sunflower(center: Vec2, radius: f32, count: usize) -> Vec<Vec2> {
+    return (0..count)
+        .map(|index| {
+            let distance = radius * ((index as f32 + 0.5) / count as f32).sqrt();
+            center + distance * Vec2::from_angle(index as f32 * GOLDEN_ANGLE)
+        })
+        .collect();
+}
+
+/// Spawns or recycles particles for `area` of soil dug out of `region`, flying away from `origin`.
+/// All given in global space.
+pub(crate) fn emit_particles(
+    commands: &mut Commands,
+    pool: &mut ParticlePool,
+    settings: &ParticleSettings,
+    region: Rect,
+    area: f32,
+    origin: Vec2,
+) {
+    let count = settings.count(area, pool.alive);
+    if count == 0 {
+        return;
+    }
+
+    let center = region.center();
+    for position in sunflower(center, region.half_size().min_element(), count) {
+        let velocity = Velocity::linear(settings.speed * (position - origin).normalize_or_zero());
+        let transform = Transform::from_translation(position.extend(0.));
+        match pool.free.pop() {
+            Some(entity) => {
+                commands.entity(entity)
+                    .remove::<(RigidBodyDisabled, ColliderDisabled)>()
+                    .insert((transform, velocity, Particle::default()));
+            }
+            None => {
+                // Locked so that soil landing on terrain slides to a stop rather than rolling away.
+                commands.spawn(RigidBody::Dynamic)
+                    .insert(LockedAxes::ROTATION_LOCKED)
+                    .insert(TransformBundle::from_transform(transform))
+                    .insert(Collider::ball(settings.radius))
+                    .insert(velocity)
+                    .insert(Particle::default());
+            }
+        }
+        pool.alive += 1;
+    }
+}
+
+pub(crate) fn update_carve_particles(
+    mut commands: Commands,
+    settings: Res<ParticleSettings>,
+    mut pool: ResMut<ParticlePool>,
+    mut carved_events: EventReader<TerrainCarved>,
+    carver_query: Query<&Transform>,
+) {
+    for carved_event in carved_events.read() {
+        let origin = carver_query.get(carved_event.carver)
+            .map(|transform| transform.translation.truncate())
+            .unwrap_or(carved_event.region.center());
+        emit_particles(&mut commands, &mut pool, &settings, carved_event.region, carved_event.removed_area, origin);
+    }
+}
+
+/// Returns particles that have come to rest, or outlived `lifetime`, to the pool.
+pub(crate) fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<ParticleSettings>,
+    mut pool: ResMut<ParticlePool>,
+    mut particle_query: Query<(Entity, &mut Particle, &Velocity), Without<RigidBodyDisabled>>,
+) {
+    for (entity, mut particle, velocity) in particle_query.iter_mut() {
+        particle.age += time.delta_seconds();
+        if velocity.linvel.length() < settings.settle_speed {
+            particle.still += time.delta_seconds();
+        } else {
+            particle.still = 0.;
+        }
+
+        if particle.still > settings.settle_time || particle.age > settings.lifetime {
+            commands.entity(entity).insert((RigidBodyDisabled, ColliderDisabled));
+            pool.free.push(entity);
+            pool.alive -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use bevy::app::{App, Startup, Update};
+    use bevy::asset::{AssetApp, AssetPlugin};
+    use bevy::math::{Rect, Vec2};
+    use bevy::prelude::{Commands, Mesh, MinimalPlugins, Res, ResMut, Transform, TransformPlugin, With};
+    use bevy::time::TimeUpdateStrategy;
+    use bevy_rapier2d::dynamics::RigidBodyDisabled;
+    use bevy_rapier2d::plugin::{NoUserData, RapierConfiguration, RapierPhysicsPlugin, TimestepMode};
+    use crate::level::TerrainMaterial;
+    use crate::particle::{Particle, ParticlePool, ParticleSettings, emit_particles, sunflower, update_particles};
+    use crate::polygon::Polygon;
+    use crate::{spawn_terrain, update_terrain_collider};
+
+    #[test]
+    fn test_count_scales_with_area() {
+        let settings = ParticleSettings { per_area: 0.5, max_per_carve: 8, budget: 256, ..ParticleSettings::default() };
+
+        assert_eq!(settings.count(0.5, 0), 0);
+        assert_eq!(settings.count(6., 0), 3);
+        assert_eq!(settings.count(100., 0), 8);
+    }
+
+    #[test]
+    fn test_count_respects_budget() {
+        let settings = ParticleSettings { per_area: 0.5, max_per_carve: 8, budget: 10, ..ParticleSettings::default() };
+
+        assert_eq!(settings.count(100., 5), 5);
+        assert_eq!(settings.count(100., 10), 0);
+    }
+
+    #[test]
+    fn test_sunflower_inside_disc() {
+        let center = Vec2::new(4., -2.);
+
+        let points = sunflower(center, 2., 32);
+
+        assert_eq!(points.len(), 32);
+        assert!(points.iter().all(|point| point.distance(center) <= 2.));
+    }
+
+    #[test]
+    fn test_settles_on_terrain() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TransformPlugin, AssetPlugin::default()))
+            .init_asset::<Mesh>()
+            .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.))
+            .insert_resource(RapierConfiguration {
+                timestep_mode: TimestepMode::Fixed { dt: 1. / 60., substeps: 1 },
+                ..RapierConfiguration::default()
+            })
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(1. / 60.)))
+            .insert_resource(ParticleSettings { lifetime: 100., ..ParticleSettings::default() })
+            .init_resource::<ParticlePool>()
+            .add_systems(Startup, |mut commands: Commands, mut pool: ResMut<ParticlePool>, settings: Res<ParticleSettings>| {
+                spawn_terrain(&mut commands, Polygon::rectangle(Vec2::new(32., 4.)), Transform::from_xyz(0., -10., 0.), TerrainMaterial::Dirt);
+                emit_particles(&mut commands, &mut pool, &settings, Rect::new(-2., -2., 2., 2.), 16., Vec2::new(0., 4.));
+            })
+            .add_systems(Update, (update_terrain_collider, update_particles));
+
+        for _ in 0..600 {
+            app.update();
+        }
+
+        let positions: Vec<f32> = app.world.query_filtered::<&Transform, (With<Particle>, With<RigidBodyDisabled>)>()
+            .iter(&app.world)
+            .map(|transform| transform.translation.y)
+            .collect();
+        assert_eq!(app.world.resource::<ParticlePool>().alive, 0);
+        assert_eq!(positions.len(), 8);
+        assert!(positions.iter().all(|y| (-8. ..-6.).contains(y)), "particles at {positions:?}");
+    }
+}
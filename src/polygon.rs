@@ -1,7 +1,10 @@
-use bevy::math::Vec2;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+use bevy::math::{Quat, Rect, Vec2};
 use bevy::prelude::{Component, Transform};
+use bevy_rapier2d::geometry::Collider;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Component, Debug, PartialEq)]
+#[derive(Clone, Component, Debug, Deserialize, PartialEq, Serialize)]
 pub(crate) struct Polygon {
     pub(crate) vertices: Vec<Vec2>,
 }
@@ -12,34 +15,292 @@ impl From<Vec<Vec2>> for Polygon {
     }
 }
 
+impl From<Rect> for Polygon {
+    fn from(rect: Rect) -> Self {
+        Polygon::from(vec![
+            Vec2::new(rect.min.x, rect.max.y),
+            rect.max,
+            Vec2::new(rect.max.x, rect.min.y),
+            rect.min,
+        ])
+    }
+}
+
+/// A polygon given by its constructor's parameters, for data files that would otherwise spell out
+/// every vertex.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) enum Shape {
+    Polygon(Polygon),
+    Rectangle(Vec2),
+    Ellipse { radii: Vec2, segments: usize },
+    Capsule { half_length: f32, radius: f32, segments: usize },
+    Regular { sides: usize, radius: f32 },
+    Star { points: usize, outer_radius: f32, inner_radius: f32 },
+}
+
+impl Shape {
+    pub(crate) fn polygon(&self) -> Polygon {
+        return match *self {
+            Shape::Polygon(ref polygon) => polygon.clone(),
+            Shape::Rectangle(size) => Polygon::rectangle(size),
+            Shape::Ellipse { radii, segments } => Polygon::ellipse(radii, segments),
+            Shape::Capsule { half_length, radius, segments } => Polygon::capsule(half_length, radius, segments),
+            Shape::Regular { sides, radius } => Polygon::regular(sides, radius),
+            Shape::Star { points, outer_radius, inner_radius } => Polygon::star(points, outer_radius, inner_radius),
+        };
+    }
+}
+
+/// Constructors. Their vertices wind clockwise, the orientation `sink` expects of both operands.
+impl Polygon {
+    /// Rectangle of `size` centered on the origin.
+    pub(crate) fn rectangle(size: Vec2) -> Polygon {
+        return Polygon::from(Rect::from_center_size(Vec2::ZERO, size));
+    }
+
+    /// Ellipse with `radii` approximated by `segments` edges, starting on the +x axis.
+    pub(crate) fn ellipse(radii: Vec2, segments: usize) -> Polygon {
+        return Polygon::from((0..segments)
+            .map(|index| radii * Vec2::from_angle(-TAU * index as f32 / segments as f32))
+            .collect::<Vec<Vec2>>());
+    }
+
+    pub(crate) fn circle(radius: f32, segments: usize) -> Polygon {
+        return Polygon::ellipse(Vec2::splat(radius), segments);
+    }
+
+    /// Capsule along the x axis whose cap centers are `half_length` from the origin, with
+    /// `segments` edges per cap.
+    pub(crate) fn capsule(half_length: f32, radius: f32, segments: usize) -> Polygon {
+        let mut vertices = vec![];
+        for (center, start_angle) in [(Vec2::new(half_length, 0.), FRAC_PI_2), (Vec2::new(-half_length, 0.), -FRAC_PI_2)] {
+            for index in 0..=segments {
+                let angle = start_angle - PI * index as f32 / segments as f32;
+                vertices.push(center + radius * Vec2::from_angle(angle));
+            }
+        }
+        return Polygon::from(vertices);
+    }
+
+    /// Regular polygon with `sides` vertices `radius` from the origin, the first pointing up.
+    pub(crate) fn regular(sides: usize, radius: f32) -> Polygon {
+        return Polygon::star_like(sides, |_| radius);
+    }
+
+    /// Star with `points` tips `outer_radius` from the origin, the first pointing up, and inner
+    /// corners `inner_radius` from it.
+    pub(crate) fn star(points: usize, outer_radius: f32, inner_radius: f32) -> Polygon {
+        return Polygon::star_like(2 * points, |index| if index % 2 == 0 { outer_radius } else { inner_radius });
+    }
+
+    fn star_like(count: usize, radius: impl Fn(usize) -> f32) -> Polygon {
+        return Polygon::from((0..count)
+            .map(|index| radius(index) * Vec2::from_angle(FRAC_PI_2 - TAU * index as f32 / count as f32))
+            .collect::<Vec<Vec2>>());
+    }
+
+    /// Outline of a ball, cuboid, capsule, triangle or convex polygon collider, with round parts
+    /// approximated by `segments` edges. Other shapes have no single outline and return `None`.
+    pub(crate) fn from_collider(collider: &Collider, segments: usize) -> Option<Polygon> {
+        if let Some(ball) = collider.as_ball() {
+            return Some(Polygon::circle(ball.radius(), segments));
+        }
+        if let Some(cuboid) = collider.as_cuboid() {
+            return Some(Polygon::rectangle(2. * cuboid.half_extents()));
+        }
+        if let Some(capsule) = collider.as_capsule() {
+            let segment = capsule.segment();
+            let axis = segment.b() - segment.a();
+            let transform = Transform::from_translation(capsule.center().extend(0.))
+                .with_rotation(Quat::from_rotation_z(Vec2::X.angle_between(axis)));
+            return Some(Polygon::capsule(capsule.half_height(), capsule.radius(), segments.div_ceil(2).max(1))
+                .to_global_space(&transform));
+        }
+        if let Some(triangle) = collider.as_triangle() {
+            return Some(Polygon::from(triangle.vertices().to_vec()).clockwise());
+        }
+        if let Some(convex_polygon) = collider.as_convex_polygon() {
+            return Some(Polygon::from(convex_polygon.points().collect::<Vec<Vec2>>()).clockwise());
+        }
+        return None;
+    }
+
+    /// Solid collider covering the polygon, split into convex parts.
+    pub(crate) fn collider(&self) -> Collider {
+        let count = self.vertices.len() as u32;
+        let indices: Vec<[u32; 2]> = (0..count).map(|index| [index, (index + 1) % count]).collect();
+        return Collider::convex_decomposition(&self.vertices, &indices);
+    }
+
+    fn clockwise(mut self) -> Polygon {
+        if self.signed_area() > 0. {
+            self.vertices.reverse();
+        }
+        return self;
+    }
+}
+
 impl Polygon {
     pub(crate) fn to_global_space(&self, transform: &Transform) -> Polygon {
         let mut global_vertices = self.vertices.clone();
-        for index in 0..global_vertices.len() {
-            global_vertices[index] = (transform.rotation * global_vertices[index].extend(0.)).truncate();
-            global_vertices[index] *= transform.scale.truncate();
-            global_vertices[index] += transform.translation.truncate();
+        for vertex in global_vertices.iter_mut() {
+            *vertex = (transform.rotation * vertex.extend(0.)).truncate();
+            *vertex *= transform.scale.truncate();
+            *vertex += transform.translation.truncate();
         }
         return Polygon::from(global_vertices);
     }
 
     pub(crate) fn to_local_space(&self, transform: Transform) -> Polygon {
         let mut local_vertices = self.vertices.clone();
-        for index in 0..local_vertices.len() {
-            local_vertices[index] -= transform.translation.truncate();
-            local_vertices[index] /= transform.scale.truncate();
-            local_vertices[index] = (transform.rotation.inverse() * local_vertices[index].extend(0.)).truncate();
+        for vertex in local_vertices.iter_mut() {
+            *vertex -= transform.translation.truncate();
+            *vertex /= transform.scale.truncate();
+            *vertex = (transform.rotation.inverse() * vertex.extend(0.)).truncate();
         }
         return Polygon::from(local_vertices);
     }
+
+    /// Bounds of the vertices; an empty polygon's is an empty rectangle at the origin.
+    pub(crate) fn aabb(&self) -> Rect {
+        let Some(&first) = self.vertices.first() else {
+            return Rect::default();
+        };
+        let mut aabb = Rect::from_center_size(first, Vec2::ZERO);
+        for vertex in self.vertices.iter().skip(1) {
+            aabb = aabb.union_point(*vertex);
+        }
+        return aabb;
+    }
+
+    /// Unsigned shoelace area, in the same space as the vertices.
+    pub(crate) fn area(&self) -> f32 {
+        return self.signed_area().abs();
+    }
+
+    /// Shoelace area, positive when the vertices wind counter-clockwise.
+    pub(crate) fn signed_area(&self) -> f32 {
+        let mut twice_area = 0.;
+        let Some(mut previous) = self.vertices.last().copied() else {
+            return 0.;
+        };
+        for vertex in self.vertices.iter() {
+            twice_area += previous.perp_dot(*vertex);
+            previous = *vertex;
+        }
+        return twice_area / 2.;
+    }
+
+    /// Even-odd point-in-polygon test, in the same space as the vertices.
+    pub(crate) fn contains(&self, point: Vec2) -> bool {
+        let mut inside = false;
+        let Some(mut previous) = self.vertices.last().copied() else {
+            return false;
+        };
+        for vertex in self.vertices.iter() {
+            if (vertex.y > point.y) != (previous.y > point.y)
+                && point.x < (previous.x - vertex.x) * (point.y - vertex.y) / (previous.y - vertex.y) + vertex.x {
+                inside = !inside;
+            }
+            previous = *vertex;
+        }
+        return inside;
+    }
+
+    /// Distance from `point` to the nearest edge, in the same space as the vertices.
+    pub(crate) fn distance_to_outline(&self, point: Vec2) -> f32 {
+        let vertices = &self.vertices;
+        return (0..vertices.len())
+            .map(|index| distance_to_segment(point, vertices[index], vertices[(index + 1) % vertices.len()]))
+            .fold(f32::INFINITY, f32::min);
+    }
+
+    /// Drops vertices that lie within `tolerance` of the outline left without them, by
+    /// Douglas-Peucker.
+    pub(crate) fn simplify(&self, tolerance: f32) -> Polygon {
+        let vertices = &self.vertices;
+        if vertices.len() <= 3 {
+            return self.clone();
+        }
+
+        // Split the outline into two chains, between its first vertex and the one farthest from it.
+        let far_index = (1..vertices.len())
+            .max_by(|a, b| vertices[0].distance_squared(vertices[*a]).total_cmp(&vertices[0].distance_squared(vertices[*b])))
+            .unwrap();
+        let mut closed = vertices.clone();
+        closed.push(vertices[0]);
+        let mut keep = vec![false; closed.len()];
+        keep[0] = true;
+        keep[far_index] = true;
+        simplify_chain(&closed, 0, far_index, tolerance, &mut keep);
+        simplify_chain(&closed, far_index, vertices.len(), tolerance, &mut keep);
+
+        return Polygon::from(vertices.iter()
+            .zip(keep)
+            .filter(|(_, keep)| *keep)
+            .map(|(vertex, _)| *vertex)
+            .collect::<Vec<Vec2>>());
+    }
+
+    /// The part of the polygon inside `rect`, by Sutherland-Hodgman. Where the polygon leaves and
+    /// comes back, the outline runs along the edge of `rect` between the two crossings.
+    pub(crate) fn clip(&self, rect: Rect) -> Polygon {
+        let mut vertices = self.vertices.clone();
+        // Each side as the axis it bounds, which way is inside and where it lies.
+        let sides = [(0, 1., rect.min.x), (0, -1., rect.max.x), (1, 1., rect.min.y), (1, -1., rect.max.y)];
+        for (axis, direction, bound) in sides {
+            let inside = |vertex: Vec2| (vertex[axis] - bound) * direction >= 0.;
+            let mut clipped = vec![];
+            for (index, &vertex) in vertices.iter().enumerate() {
+                let previous = vertices[(index + vertices.len() - 1) % vertices.len()];
+                if inside(vertex) != inside(previous) {
+                    let along = (bound - previous[axis]) / (vertex[axis] - previous[axis]);
+                    clipped.push(previous.lerp(vertex, along));
+                }
+                if inside(vertex) {
+                    clipped.push(vertex);
+                }
+            }
+            vertices = clipped;
+        }
+        return Polygon::from(vertices);
+    }
+}
+
+/// Marks in `keep` the vertices strictly between `start` and `end` needed to stay within
+/// `tolerance` of the chain.
+fn simplify_chain(vertices: &[Vec2], start: usize, end: usize, tolerance: f32, keep: &mut [bool]) {
+    let (a, b) = (vertices[start], vertices[end]);
+    let farthest = (start + 1..end)
+        .map(|index| (index, distance_to_segment(vertices[index], a, b)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+    let Some((index, distance)) = farthest else {
+        return;
+    };
+    if distance <= tolerance {
+        return;
+    }
+    keep[index] = true;
+    simplify_chain(vertices, start, index, tolerance, keep);
+    simplify_chain(vertices, index, end, tolerance, keep);
+}
+
+fn distance_to_segment(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let length_squared = a.distance_squared(b);
+    if length_squared == 0. {
+        return point.distance(a);
+    }
+    let along = ((point - a).dot(b - a) / length_squared).clamp(0., 1.);
+    return point.distance(a + along * (b - a));
 }
 
 #[cfg(test)]
 mod tests {
     use std::f32::consts::PI;
-    use bevy::math::{Quat, Vec2, Vec3};
+    use bevy::math::{Quat, Rect, Vec2, Vec3};
     use bevy::prelude::Transform;
-    use crate::polygon::Polygon;
+    use bevy_rapier2d::geometry::Collider;
+    use crate::polygon::{Polygon, Shape};
 
     #[test]
     fn test_translation_scale() {
@@ -112,4 +373,176 @@ mod tests {
         assert_eq!(actual_global, global);
         assert_eq!(actual_local, local);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_aabb() {
+        let polygon = Polygon::from(vec![
+            Vec2::new(-1., 0.),
+            Vec2::new(0., 3.),
+            Vec2::new(2., -1.),
+        ]);
+
+        assert_eq!(polygon.aabb(), Rect::new(-1., -1., 2., 3.));
+    }
+
+    #[test]
+    fn test_empty() {
+        let polygon = Polygon::from(vec![]);
+
+        assert!(polygon.aabb().is_empty());
+        assert_eq!(polygon.area(), 0.);
+        assert!(!polygon.contains(Vec2::ZERO));
+    }
+
+    #[test]
+    fn test_area() {
+        let polygon = Polygon::from(vec![
+            Vec2::new(2., 2.),
+            Vec2::new(2., -2.),
+            Vec2::new(-2., -2.),
+            Vec2::new(-2., 2.),
+            Vec2::new(-1., 2.),
+            Vec2::new(-1., 1.),
+            Vec2::new(1., 1.),
+            Vec2::new(1., 2.),
+        ]);
+
+        assert_eq!(polygon.area(), 14.);
+    }
+
+    #[test]
+    fn test_contains() {
+        let polygon = Polygon::from(vec![
+            Vec2::new(2., 2.),
+            Vec2::new(2., -2.),
+            Vec2::new(-2., -2.),
+            Vec2::new(-2., 2.),
+            Vec2::new(-1., 2.),
+            Vec2::new(-1., 1.),
+            Vec2::new(1., 1.),
+            Vec2::new(1., 2.),
+        ]);
+
+        assert!(polygon.contains(Vec2::new(0., 0.)));
+        assert!(polygon.contains(Vec2::new(1.5, 1.5)));
+        assert!(!polygon.contains(Vec2::new(0., 1.5)));
+        assert!(!polygon.contains(Vec2::new(3., 0.)));
+    }
+
+    #[test]
+    fn test_rectangle() {
+        let expected = Polygon::from(vec![
+            Vec2::new(-2., 1.),
+            Vec2::new(2., 1.),
+            Vec2::new(2., -1.),
+            Vec2::new(-2., -1.),
+        ]);
+
+        assert_eq!(Polygon::rectangle(Vec2::new(4., 2.)), expected);
+    }
+
+    #[test]
+    fn test_circle() {
+        let circle = Polygon::circle(2., 32);
+
+        assert_eq!(circle.vertices.len(), 32);
+        assert_eq!(circle.vertices[0], Vec2::new(2., 0.));
+        assert!(circle.vertices.iter().all(|vertex| (vertex.length() - 2.).abs() < 0.0001));
+        assert!(circle.signed_area() < 0.);
+        assert!((circle.area() - PI * 4.).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_ellipse() {
+        let ellipse = Polygon::ellipse(Vec2::new(4., 1.), 4);
+
+        assert!(ellipse.vertices[1].abs_diff_eq(Vec2::new(0., -1.), 0.0001));
+        assert!(ellipse.vertices[2].abs_diff_eq(Vec2::new(-4., 0.), 0.0001));
+    }
+
+    #[test]
+    fn test_capsule() {
+        let capsule = Polygon::capsule(2., 1., 16);
+
+        assert_eq!(capsule.vertices.len(), 34);
+        assert_eq!(capsule.aabb(), Rect::new(-3., -1., 3., 1.));
+        assert!(capsule.signed_area() < 0.);
+        assert!((capsule.area() - (8. + PI)).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_regular() {
+        let hexagon = Polygon::regular(6, 1.);
+
+        assert_eq!(hexagon.vertices.len(), 6);
+        assert!(hexagon.vertices[0].abs_diff_eq(Vec2::Y, 0.0001));
+        assert!(hexagon.signed_area() < 0.);
+    }
+
+    #[test]
+    fn test_star() {
+        let star = Polygon::star(5, 2., 1.);
+
+        assert_eq!(star.vertices.len(), 10);
+        assert!(star.vertices.iter().step_by(2).all(|vertex| (vertex.length() - 2.).abs() < 0.0001));
+        assert!(star.vertices.iter().skip(1).step_by(2).all(|vertex| (vertex.length() - 1.).abs() < 0.0001));
+        assert!(star.signed_area() < 0.);
+    }
+
+    #[test]
+    fn test_from_collider() {
+        assert_eq!(Polygon::from_collider(&Collider::cuboid(2., 1.), 8), Some(Polygon::rectangle(Vec2::new(4., 2.))));
+        assert_eq!(Polygon::from_collider(&Collider::ball(1.), 8), Some(Polygon::circle(1., 8)));
+
+        let capsule = Polygon::from_collider(&Collider::capsule_y(2., 1.), 8).unwrap();
+        assert!(capsule.aabb().min.abs_diff_eq(Vec2::new(-1., -3.), 0.0001));
+        assert!(capsule.aabb().max.abs_diff_eq(Vec2::new(1., 3.), 0.0001));
+
+        let triangle = Polygon::from_collider(&Collider::triangle(Vec2::ZERO, Vec2::X, Vec2::Y), 8).unwrap();
+        assert!(triangle.signed_area() < 0.);
+
+        assert_eq!(Polygon::from_collider(&Collider::halfspace(Vec2::Y).unwrap(), 8), None);
+    }
+
+    #[test]
+    fn test_shape_from_ron() {
+        let shape: Shape = ron::from_str("Star(points: 5, outer_radius: 2.0, inner_radius: 1.0)").unwrap();
+
+        assert_eq!(shape.polygon(), Polygon::star(5, 2., 1.));
+    }
+
+    #[test]
+    fn test_simplify() {
+        let square = Polygon::from(vec![
+            Vec2::new(0., 0.),
+            Vec2::new(0., 1.),
+            Vec2::new(0., 2.),
+            Vec2::new(1., 2.1),
+            Vec2::new(2., 2.),
+            Vec2::new(2., 0.),
+            Vec2::new(1., 0.),
+        ]);
+
+        let actual = square.simplify(0.25);
+
+        assert_eq!(actual, Polygon::from(vec![
+            Vec2::new(0., 0.),
+            Vec2::new(0., 2.),
+            Vec2::new(2., 2.),
+            Vec2::new(2., 0.),
+        ]));
+        assert_eq!(square.simplify(0.05).vertices.len(), 5);
+    }
+
+    #[test]
+    fn test_clip() {
+        let square = Polygon::rectangle(Vec2::splat(4.));
+
+        let actual = square.clip(Rect::new(0., -1., 3., 1.));
+
+        assert!((actual.area() - 4.).abs() < 0.0001);
+        assert!(actual.contains(Vec2::new(1., 0.)));
+        assert!(!actual.contains(Vec2::new(-1., 0.)));
+        assert!(square.clip(Rect::new(5., 5., 6., 6.)).vertices.is_empty());
+    }
+}
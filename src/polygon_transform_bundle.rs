@@ -1,15 +1,12 @@
-use bevy::math::Vec2;
+use bevy::math::{DQuat, DVec2, Vec2};
 use bevy::prelude::Transform;
-use bevy_rapier2d::na::Point2;
-use bevy_rapier2d::parry::shape::SegmentPointLocation;
-use bevy_rapier2d::parry::utils::segments_intersection2d;
-use bevy_rapier2d::parry::utils::SegmentsIntersection::Point;
+use serde::{Deserialize, Serialize};
 use crate::polygon::Polygon;
 
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) struct PolygonTransformBundle {
     pub(crate) polygon: Polygon,
-    transform: Transform,
+    pub(crate) transform: Transform,
 }
 
 impl From<(Polygon, Transform)> for PolygonTransformBundle {
@@ -18,94 +15,267 @@ impl From<(Polygon, Transform)> for PolygonTransformBundle {
     }
 }
 
+/// Tolerances for `sink`, which works in f64 whatever the precision the terrain is stored in. Read
+/// from the tuning file.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub(crate) struct Precision {
+    /// Edges whose directions differ by less than this, as the sine of the angle between them, are
+    /// treated as parallel and not crossing, however long they are.
+    pub(crate) epsilon: f64,
+    /// Size of the local-space grid carved outlines snap to, so error can't build up carve after
+    /// carve. Zero leaves vertices where they fall.
+    pub(crate) snap: f32,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Precision {
+            epsilon: 0.000001,
+            snap: 0.,
+        }
+    }
+}
+
+impl Precision {
+    fn snap(&self, polygon: Polygon) -> Polygon {
+        if self.snap <= 0. {
+            return polygon;
+        }
+        let mut vertices: Vec<Vec2> = polygon.vertices.into_iter()
+            .map(|vertex| (vertex / self.snap).round() * self.snap)
+            .collect();
+        vertices.dedup();
+        while vertices.len() > 1 && vertices.first() == vertices.last() {
+            vertices.pop();
+        }
+        return Polygon::from(vertices);
+    }
+}
+
 impl PolygonTransformBundle {
-    pub(crate) fn sink(self, bounds: &PolygonTransformBundle) -> Self {
-        let vertices = self.polygon.to_global_space(&self.transform).vertices;
-        let bounds_vertices = bounds.polygon.to_global_space(&bounds.transform).vertices;
-
-        let mut new_vertices = vec![];
-        let mut is_tracing_self = true;
-        let mut start_index = 0;
-        let mut end_index = 1;
-        let mut start_bounds_index = 1;
-        let mut end_bounds_index = 0;
-        let mut intersection = None;
-        for _ in 0..bounds_vertices.len() {
-            let start_bounds = bounds_vertices[start_bounds_index];
-            let end_bounds = bounds_vertices[end_bounds_index];
-            intersection = my_intersection(vertices[0], vertices[1], start_bounds, end_bounds)
-                .filter(|_| cross(vertices[0], vertices[1], start_bounds) < 0.);
-            if intersection.is_some() { break; }
+    /// Subtracts `bounds` from this polygon, returning every piece left over when `bounds` cuts it
+    /// apart and nothing at all when `bounds` swallows it whole.
+    pub(crate) fn sink(self, bounds: &PolygonTransformBundle) -> Vec<Self> {
+        return self.sink_with(bounds, &Precision::default());
+    }
 
-            start_bounds_index = end_bounds_index;
-            end_bounds_index = (end_bounds_index + bounds_vertices.len() - 1) % bounds_vertices.len();
+    /// `sink` with the given tolerances.
+    ///
+    /// The carve happens in this polygon's local space, with `bounds` brought into it, so vertices
+    /// `bounds` doesn't touch come back bit-for-bit as they went in.
+    pub(crate) fn sink_with(self, bounds: &PolygonTransformBundle, precision: &Precision) -> Vec<Self> {
+        let vertices: Vec<DVec2> = self.polygon.vertices.iter().map(|vertex| vertex.as_dvec2()).collect();
+        let bounds_vertices = to_local_space(&to_global_space(&bounds.polygon, &bounds.transform), &self.transform);
+        let inside: Vec<bool> = vertices.iter().map(|vertex| contains(&bounds_vertices, *vertex)).collect();
+        let mut visited = vec![false; vertices.len()];
+        let mut pieces = vec![];
+
+        let intersection = first_exit(&vertices, &bounds_vertices, precision.epsilon);
+        if !inside[0] || intersection.is_some() {
+            match trace(&vertices, &bounds_vertices, 0, intersection, &mut visited, precision.epsilon) {
+                Some(new_vertices) => pieces.push(new_vertices),
+                None => return vec![self],
+            }
         }
 
-        while new_vertices.is_empty() || start_index != 0 || !is_tracing_self {
-            if is_tracing_self {
-                let start = intersection.unwrap_or(vertices[start_index]);
-                let end = vertices[end_index];
-                new_vertices.push(start);
-                for _ in 0..bounds_vertices.len() {
-                    let start_bounds = bounds_vertices[start_bounds_index];
-                    let end_bounds = bounds_vertices[end_bounds_index];
-                    intersection = my_intersection(start, end, start_bounds, end_bounds)
-                        .filter(|_| cross(start, end, start_bounds) > 0.);
-                    if intersection.is_some() { break; }
-
-                    start_bounds_index = end_bounds_index;
-                    end_bounds_index = (end_bounds_index + bounds_vertices.len() - 1) % bounds_vertices.len();
-                }
-            } else {
-                let start_bounds = intersection.unwrap_or(bounds_vertices[start_bounds_index]);
-                new_vertices.push(start_bounds);
-                for _ in 0..vertices.len() {
-                    intersection = my_intersection(
-                        start_bounds,
-                        bounds_vertices[end_bounds_index],
-                        vertices[start_index],
-                        vertices[end_index],
-                    );
-                    if intersection.is_some() { break; }
-
-                    start_index = end_index;
-                    end_index = (end_index + 1) % vertices.len();
-                }
+        while let Some(start_index) = (0..vertices.len()).find(|&index| !visited[index] && !inside[index]) {
+            match trace(&vertices, &bounds_vertices, start_index, None, &mut visited, precision.epsilon) {
+                Some(new_vertices) => pieces.push(new_vertices),
+                None => return vec![self],
             }
+        }
+
+        // A single walk over every vertex, none of them inside `bounds` and no crossings added, means
+        // `bounds` missed: hand back the original as it was, without snapping it.
+        if pieces.len() == 1 && pieces[0].len() == vertices.len() && !inside.contains(&true) {
+            return vec![self];
+        }
+
+        return pieces.into_iter()
+            .map(|new_vertices| PolygonTransformBundle {
+                polygon: precision.snap(Polygon::from(new_vertices.iter()
+                    .map(|vertex| vertex.as_vec2())
+                    .collect::<Vec<Vec2>>())),
+                transform: self.transform,
+            })
+            .filter(|piece| piece.polygon.vertices.len() >= 3)
+            .collect();
+    }
+
+    /// Area in global space.
+    pub(crate) fn area(&self) -> f32 {
+        return self.polygon.to_global_space(&self.transform).area();
+    }
+}
+
+/// `Polygon::to_global_space` carried out in f64.
+fn to_global_space(polygon: &Polygon, transform: &Transform) -> Vec<DVec2> {
+    let rotation = transform.rotation.as_f64();
+    let scale = transform.scale.truncate().as_dvec2();
+    let translation = transform.translation.truncate().as_dvec2();
+    return polygon.vertices.iter()
+        .map(|vertex| rotate(rotation, vertex.as_dvec2()) * scale + translation)
+        .collect();
+}
+
+/// `Polygon::to_local_space` carried out in f64.
+fn to_local_space(vertices: &[DVec2], transform: &Transform) -> Vec<DVec2> {
+    let rotation = transform.rotation.as_f64().inverse();
+    let scale = transform.scale.truncate().as_dvec2();
+    let translation = transform.translation.truncate().as_dvec2();
+    return vertices.iter()
+        .map(|vertex| rotate(rotation, (*vertex - translation) / scale))
+        .collect();
+}
+
+fn rotate(rotation: DQuat, vertex: DVec2) -> DVec2 {
+    return (rotation * vertex.extend(0.)).truncate();
+}
+
+/// `Polygon::contains` carried out in f64.
+fn contains(vertices: &[DVec2], point: DVec2) -> bool {
+    let mut inside = false;
+    let mut previous = vertices[vertices.len() - 1];
+    for vertex in vertices.iter() {
+        if (vertex.y > point.y) != (previous.y > point.y)
+            && point.x < (previous.x - vertex.x) * (point.y - vertex.y) / (previous.y - vertex.y) + vertex.x {
+            inside = !inside;
+        }
+        previous = *vertex;
+    }
+    return inside;
+}
+
+/// Where the edge from vertex 0 to vertex 1 leaves `bounds_vertices`, when vertex 0 starts inside
+/// it.
+fn first_exit(vertices: &[DVec2], bounds_vertices: &[DVec2], epsilon: f64) -> Option<DVec2> {
+    if !contains(bounds_vertices, vertices[0]) {
+        return None;
+    }
+
+    let mut start_bounds_index = 1;
+    let mut end_bounds_index = 0;
+    for _ in 0..bounds_vertices.len() {
+        let start_bounds = bounds_vertices[start_bounds_index];
+        let end_bounds = bounds_vertices[end_bounds_index];
+        let intersection = my_intersection(vertices[0], vertices[1], start_bounds, end_bounds, epsilon)
+            .filter(|_| cross(vertices[0], vertices[1], start_bounds) < 0.);
+        if intersection.is_some() { return intersection; }
+
+        start_bounds_index = end_bounds_index;
+        end_bounds_index = (end_bounds_index + bounds_vertices.len() - 1) % bounds_vertices.len();
+    }
+    return None;
+}
+
+/// Walks the outline of `vertices` minus `bounds_vertices` from the edge at `start_index`
+/// (starting at `intersection` along it, if given) until it arrives back where it started,
+/// marking each vertex of `vertices` it passes in `visited`. Returns `None` if the walk doesn't
+/// close, which degenerate inputs such as vertices lying exactly on the bounds can cause.
+fn trace(
+    vertices: &[DVec2],
+    bounds_vertices: &[DVec2],
+    start_index: usize,
+    mut intersection: Option<DVec2>,
+    visited: &mut [bool],
+    epsilon: f64,
+) -> Option<Vec<DVec2>> {
+    let first_index = start_index;
+    let starts_on_intersection = intersection.is_some();
+    let max_vertices = 2 * (vertices.len() + 1) * (bounds_vertices.len() + 1);
+
+    let mut new_vertices = vec![];
+    let mut is_tracing_self = true;
+    let mut start_index = start_index;
+    let mut end_index = (start_index + 1) % vertices.len();
+    let mut start_bounds_index = 1;
+    let mut end_bounds_index = 0;
+
+    while new_vertices.is_empty()
+        || start_index != first_index
+        || !is_tracing_self
+        || intersection.is_some() != starts_on_intersection {
+        if new_vertices.len() > max_vertices {
+            return None;
+        }
+
+        if is_tracing_self {
+            if intersection.is_none() {
+                visited[start_index] = true;
+            }
+            let start = intersection.unwrap_or(vertices[start_index]);
+            let end = vertices[end_index];
+            new_vertices.push(start);
+            for _ in 0..bounds_vertices.len() {
+                let start_bounds = bounds_vertices[start_bounds_index];
+                let end_bounds = bounds_vertices[end_bounds_index];
+                intersection = my_intersection(start, end, start_bounds, end_bounds, epsilon)
+                    .filter(|_| cross(start, end, start_bounds) > 0.);
+                if intersection.is_some() { break; }
 
-            if intersection.is_some() {
-                is_tracing_self = !is_tracing_self;
-            } else if is_tracing_self {
-                start_index = end_index;
-                end_index = (end_index + 1) % vertices.len();
-            } else {
                 start_bounds_index = end_bounds_index;
                 end_bounds_index = (end_bounds_index + bounds_vertices.len() - 1) % bounds_vertices.len();
             }
+        } else {
+            let start_bounds = intersection.unwrap_or(bounds_vertices[start_bounds_index]);
+            new_vertices.push(start_bounds);
+            for _ in 0..vertices.len() {
+                intersection = my_intersection(
+                    start_bounds,
+                    bounds_vertices[end_bounds_index],
+                    vertices[start_index],
+                    vertices[end_index],
+                    epsilon,
+                );
+                if intersection.is_some() { break; }
+
+                start_index = end_index;
+                end_index = (end_index + 1) % vertices.len();
+            }
         }
 
-        return PolygonTransformBundle {
-            polygon: Polygon::from(new_vertices).to_local_space(self.transform),
-            transform: self.transform,
-        };
+        if intersection.is_some() {
+            is_tracing_self = !is_tracing_self;
+        } else if is_tracing_self {
+            start_index = end_index;
+            end_index = (end_index + 1) % vertices.len();
+        } else {
+            start_bounds_index = end_bounds_index;
+            end_bounds_index = (end_bounds_index + bounds_vertices.len() - 1) % bounds_vertices.len();
+        }
     }
+
+    return Some(new_vertices);
 }
 
-fn my_intersection(a_start: Vec2, a_end: Vec2, b_start: Vec2, b_end: Vec2) -> Option<Vec2> {
-    match segments_intersection2d(
-        &Point2::from(a_start),
-        &Point2::from(a_end),
-        &Point2::from(b_start),
-        &Point2::from(b_end),
-        0.000001,
-    ) {
-        Some(Point { loc1: SegmentPointLocation::OnEdge([_, from_start]), .. }) =>
-            Some(a_start + from_start * (a_end - a_start)),
-        _ => None
+/// Where segment a crosses segment b, if it does so strictly between a's ends. Segments within
+/// `epsilon` of parallel, as the sine of the angle between them, never cross. Follows parry's
+/// `segments_intersection2d`, in f64.
+fn my_intersection(a_start: DVec2, a_end: DVec2, b_start: DVec2, b_end: DVec2, epsilon: f64) -> Option<DVec2> {
+    let denominator = a_start.x * (b_end.y - b_start.y)
+        + a_end.x * (b_start.y - b_end.y)
+        + b_end.x * (a_end.y - a_start.y)
+        + b_start.x * (a_start.y - a_end.y);
+    // The denominator is the cross product of the two directions, so scale it down by their lengths
+    // before comparing.
+    if denominator.abs() <= epsilon * (a_end - a_start).length() * (b_end - b_start).length() {
+        return None;
+    }
+
+    let from_start = (a_start.x * (b_end.y - b_start.y)
+        + b_start.x * (a_start.y - b_end.y)
+        + b_end.x * (b_start.y - a_start.y)) / denominator;
+    let from_b_start = -(a_start.x * (b_start.y - a_end.y)
+        + a_end.x * (a_start.y - b_start.y)
+        + b_start.x * (a_end.y - a_start.y)) / denominator;
+    if from_start <= 0. || from_start >= 1. || !(0. ..=1.).contains(&from_b_start) {
+        return None;
     }
+    return Some(a_start + from_start * (a_end - a_start));
 }
 
-fn cross(a_start: Vec2, a_end: Vec2, b_start: Vec2) -> f32 {
+fn cross(a_start: DVec2, a_end: DVec2, b_start: DVec2) -> f64 {
     (a_end.x - a_start.x) * (b_start.y - a_start.y) - (a_end.y - a_start.y) * (b_start.x - a_start.x)
 }
 
@@ -113,13 +283,13 @@ fn cross(a_start: Vec2, a_end: Vec2, b_start: Vec2) -> f32 {
 mod tests {
     use std::env::current_dir;
     use std::io;
-    use bevy::math::Vec2;
+    use bevy::math::{DVec2, Quat, Vec2, Vec3};
     use bevy::prelude::Transform;
     use svg::Document;
     use svg::node::element::Path;
     use svg::node::element::path::Data;
     use crate::polygon::{Polygon};
-    use crate::polygon_transform_bundle::PolygonTransformBundle;
+    use crate::polygon_transform_bundle::{PolygonTransformBundle, Precision, my_intersection};
 
     fn svg_path(bundle: &PolygonTransformBundle, stroke: &str, stroke_width: f64) -> Path {
         return Path::new()
@@ -173,7 +343,7 @@ mod tests {
         };
 
         let actual = left_operand.clone().sink(&right_operand);
-        let expected = PolygonTransformBundle {
+        let expected = vec![PolygonTransformBundle {
             polygon: Polygon::from(vec![
                 Vec2::new(2., 1.),
                 Vec2::new(2., -2.),
@@ -183,12 +353,12 @@ mod tests {
                 Vec2::new(1., 1.),
             ]),
             transform: Transform::from_xyz(0., 0., 0.),
-        };
+        }];
 
         let scene = Document::new()
             .set("viewBox", (-3, -3, 6, 6))
-            .add(svg_path(&actual, "red", 0.25))
-            .add(svg_path(&expected, "green", 0.125))
+            .add(svg_path(&actual[0], "red", 0.25))
+            .add(svg_path(&expected[0], "green", 0.125))
             .add(svg_path(&left_operand, "black", 0.125 / 4.))
             .add(svg_path(&right_operand, "white", 0.125 / 4.))
             ;
@@ -219,7 +389,7 @@ mod tests {
         };
 
         let actual = left_operand.clone().sink(&right_operand);
-        let expected = PolygonTransformBundle {
+        let expected = vec![PolygonTransformBundle {
             polygon: Polygon::from(vec![
                 Vec2::new(2., 2.),
                 Vec2::new(2., -2.),
@@ -231,12 +401,12 @@ mod tests {
                 Vec2::new(1., 2.),
             ]),
             transform: Transform::from_xyz(0., 0., 0.),
-        };
+        }];
 
         let scene = Document::new()
             .set("viewBox", (-3, -3, 6, 6))
-            .add(svg_path(&actual, "red", 0.25))
-            .add(svg_path(&expected, "green", 0.125))
+            .add(svg_path(&actual[0], "red", 0.25))
+            .add(svg_path(&expected[0], "green", 0.125))
             .add(svg_path(&left_operand, "black", 0.125 / 4.))
             .add(svg_path(&right_operand, "white", 0.125 / 4.))
             ;
@@ -271,7 +441,7 @@ mod tests {
         };
 
         let actual = left_operand.clone().sink(&right_operand);
-        let expected = PolygonTransformBundle {
+        let expected = vec![PolygonTransformBundle {
             polygon: Polygon::from(vec![
                 Vec2::new(2., 2.),
                 Vec2::new(2., 0.),
@@ -287,17 +457,254 @@ mod tests {
                 Vec2::new(1., 2.),
             ]),
             transform: Transform::from_xyz(1., 0., 0.),
-        };
+        }];
 
 
         let scene = Document::new()
             .set("viewBox", (-3, -4, 7, 6))
-            .add(svg_path(&actual, "red", 0.25))
-            .add(svg_path(&expected, "green", 0.125))
+            .add(svg_path(&actual[0], "red", 0.25))
+            .add(svg_path(&expected[0], "green", 0.125))
             .add(svg_path(&left_operand, "black", 0.125 / 4.))
             .add(svg_path(&right_operand, "white", 0.125 / 4.))
             ;
 
         assert_eq!(actual, expected, "Visual: {:?}", save_svg(scene, "test_sink_double_subtract"))
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_sink_split() {
+        let left_operand = PolygonTransformBundle {
+            polygon: Polygon::from(vec![
+                Vec2::new(-2., 2.),
+                Vec2::new(2., 2.),
+                Vec2::new(2., -2.),
+                Vec2::new(-2., -2.),
+            ]),
+            transform: Transform::from_xyz(0., 0., 0.),
+        };
+
+        let right_operand = PolygonTransformBundle {
+            polygon: Polygon::from(vec![
+                Vec2::new(-0.5, 3.),
+                Vec2::new(0.5, 3.),
+                Vec2::new(0.5, -3.),
+                Vec2::new(-0.5, -3.),
+            ]),
+            transform: Transform::from_xyz(0., 0., 0.),
+        };
+
+        let actual = left_operand.clone().sink(&right_operand);
+        let expected = vec![
+            PolygonTransformBundle {
+                polygon: Polygon::from(vec![
+                    Vec2::new(-2., 2.),
+                    Vec2::new(-0.5, 2.),
+                    Vec2::new(-0.5, -2.),
+                    Vec2::new(-2., -2.),
+                ]),
+                transform: Transform::from_xyz(0., 0., 0.),
+            },
+            PolygonTransformBundle {
+                polygon: Polygon::from(vec![
+                    Vec2::new(2., 2.),
+                    Vec2::new(2., -2.),
+                    Vec2::new(0.5, -2.),
+                    Vec2::new(0.5, 2.),
+                ]),
+                transform: Transform::from_xyz(0., 0., 0.),
+            },
+        ];
+
+        let mut scene = Document::new()
+            .set("viewBox", (-3, -3, 6, 6));
+        for piece in actual.iter() {
+            scene = scene.add(svg_path(piece, "red", 0.25));
+        }
+        for piece in expected.iter() {
+            scene = scene.add(svg_path(piece, "green", 0.125));
+        }
+        let scene = scene
+            .add(svg_path(&left_operand, "black", 0.125 / 4.))
+            .add(svg_path(&right_operand, "white", 0.125 / 4.))
+            ;
+
+        assert_eq!(actual, expected, "Visual: {:?}", save_svg(scene, "test_sink_split"))
+    }
+
+    #[test]
+    fn test_sink_notch_first_edge() {
+        let left_operand = PolygonTransformBundle {
+            polygon: Polygon::from(vec![
+                Vec2::new(-2., 2.),
+                Vec2::new(2., 2.),
+                Vec2::new(2., -2.),
+                Vec2::new(-2., -2.),
+            ]),
+            transform: Transform::from_xyz(0., 0., 0.),
+        };
+
+        let right_operand = PolygonTransformBundle {
+            polygon: Polygon::from(vec![
+                Vec2::new(-1., 3.),
+                Vec2::new(1., 3.),
+                Vec2::new(1., 1.),
+                Vec2::new(-1., 1.),
+            ]),
+            transform: Transform::from_xyz(0., 0., 0.),
+        };
+
+        let actual = left_operand.clone().sink(&right_operand);
+        let expected = vec![PolygonTransformBundle {
+            polygon: Polygon::from(vec![
+                Vec2::new(-2., 2.),
+                Vec2::new(-1., 2.),
+                Vec2::new(-1., 1.),
+                Vec2::new(1., 1.),
+                Vec2::new(1., 2.),
+                Vec2::new(2., 2.),
+                Vec2::new(2., -2.),
+                Vec2::new(-2., -2.),
+            ]),
+            transform: Transform::from_xyz(0., 0., 0.),
+        }];
+
+        let scene = Document::new()
+            .set("viewBox", (-3, -3, 6, 6))
+            .add(svg_path(&actual[0], "red", 0.25))
+            .add(svg_path(&expected[0], "green", 0.125))
+            .add(svg_path(&left_operand, "black", 0.125 / 4.))
+            .add(svg_path(&right_operand, "white", 0.125 / 4.))
+            ;
+
+        assert_eq!(actual, expected, "Visual: {:?}", save_svg(scene, "test_sink_notch_first_edge"))
+    }
+
+    #[test]
+    fn test_sink_swallowed() {
+        let left_operand = PolygonTransformBundle {
+            polygon: Polygon::from(vec![
+                Vec2::new(-1., 1.),
+                Vec2::new(1., 1.),
+                Vec2::new(1., -1.),
+                Vec2::new(-1., -1.),
+            ]),
+            transform: Transform::from_xyz(0., 0., 0.),
+        };
+
+        let right_operand = PolygonTransformBundle {
+            polygon: Polygon::from(vec![
+                Vec2::new(-2., 2.),
+                Vec2::new(2., 2.),
+                Vec2::new(2., -2.),
+                Vec2::new(-2., -2.),
+            ]),
+            transform: Transform::from_xyz(0., 0., 0.),
+        };
+
+        assert_eq!(left_operand.sink(&right_operand), vec![]);
+    }
+
+    #[test]
+    fn test_sink_missed_returns_original() {
+        let round_chunk = PolygonTransformBundle::from((
+            Polygon::circle(0.5, 32),
+            Transform::from_xyz(3.1, -7.7, 0.)
+                .with_rotation(Quat::from_rotation_z(0.7))
+                .with_scale(Vec3::splat(8.)),
+        ));
+        // Inside the chunk's bounds but outside the circle.
+        let bounds = PolygonTransformBundle::from((
+            Polygon::rectangle(Vec2::splat(0.5)),
+            Transform::from_translation(round_chunk.transform.transform_point(Vec3::new(0.48, 0.48, 0.))),
+        ));
+
+        assert_eq!(round_chunk.clone().sink(&bounds), vec![round_chunk]);
+    }
+
+    #[test]
+    fn test_sink_repeated_does_not_drift() {
+        let transform = Transform::from_xyz(3.1, -7.3, 0.)
+            .with_rotation(Quat::from_rotation_z(0.7))
+            .with_scale(Vec3::splat(16.));
+        let original = Polygon::circle(0.5, 64);
+        let mut terrain = PolygonTransformBundle::from((original.clone(), transform));
+
+        // Nibble at one spot on the rim, far from the vertices on the opposite side.
+        for index in 0..100 {
+            let center = transform.transform_point(Vec3::new(0.5, 0., 0.)).truncate();
+            let bite = PolygonTransformBundle::from((
+                Polygon::rectangle(Vec2::splat(0.5)),
+                Transform::from_translation(center.extend(0.)).with_rotation(Quat::from_rotation_z(index as f32 * 0.1)),
+            ));
+            terrain = terrain.sink(&bite).remove(0);
+        }
+
+        let left_side = &original.vertices[24..40];
+        assert!(left_side.iter().all(|vertex| terrain.polygon.vertices.contains(vertex)));
+    }
+
+    #[test]
+    fn test_sink_untouched_vertices_bit_identical() {
+        let transform = Transform::from_xyz(-5.3, 11.9, 0.)
+            .with_rotation(Quat::from_rotation_z(-1.3))
+            .with_scale(Vec3::new(24., 10., 1.));
+        let original = Polygon::circle(0.5, 128);
+        let mut terrain = PolygonTransformBundle::from((original.clone(), transform));
+
+        // Bites all over the right half, each in the terrain's own frame.
+        for index in 0..50 {
+            let angle = -1.2 + 2.4 * index as f32 / 50.;
+            let center = transform.transform_point((0.5 * Vec2::from_angle(angle)).extend(0.)).truncate();
+            let bite = PolygonTransformBundle::from((
+                Polygon::rectangle(Vec2::splat(1.)),
+                Transform::from_translation(center.extend(0.)).with_rotation(Quat::from_rotation_z(angle)),
+            ));
+            terrain = terrain.sink(&bite).remove(0);
+        }
+
+        let bits = |vertex: &Vec2| (vertex.x.to_bits(), vertex.y.to_bits());
+        let actual: Vec<_> = terrain.polygon.vertices.iter().map(bits).collect();
+        for vertex in original.vertices.iter().filter(|vertex| vertex.x < 0.) {
+            assert!(actual.contains(&bits(vertex)), "{vertex:?} moved");
+        }
+    }
+
+    #[test]
+    fn test_sink_snap() {
+        let left_operand = PolygonTransformBundle {
+            polygon: Polygon::rectangle(Vec2::splat(4.)),
+            transform: Transform::from_xyz(0., 0., 0.),
+        };
+        let right_operand = PolygonTransformBundle {
+            polygon: Polygon::rectangle(Vec2::splat(2.)),
+            transform: Transform::from_xyz(2.1, 2.3, 0.),
+        };
+        let precision = Precision { snap: 0.5, ..Precision::default() };
+
+        let actual = left_operand.sink_with(&right_operand, &precision);
+
+        assert_eq!(actual.len(), 1);
+        assert!(actual[0].polygon.vertices.iter().all(|vertex| *vertex == (*vertex * 2.).round() / 2.));
+        assert!(actual[0].polygon.vertices.contains(&Vec2::new(1., 1.5)));
+    }
+
+    #[test]
+    fn test_intersection_epsilon_ignores_length() {
+        let epsilon = Precision::default().epsilon;
+        for scale in [0.0001, 1., 10000.] {
+            let crossing = my_intersection(
+                DVec2::new(-scale, 0.), DVec2::new(scale, 0.),
+                DVec2::new(0., -scale), DVec2::new(0., scale),
+                epsilon,
+            );
+            let parallel = my_intersection(
+                DVec2::new(-scale, 0.), DVec2::new(scale, 0.),
+                DVec2::new(-scale, -scale * 1e-9), DVec2::new(scale, scale * 1e-9),
+                epsilon,
+            );
+
+            assert_eq!(crossing, Some(DVec2::ZERO), "scale {scale}");
+            assert_eq!(parallel, None, "scale {scale}");
+        }
+    }
+}
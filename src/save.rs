@@ -0,0 +1,437 @@
+use std::{fmt, fs, io};
+use std::path::Path;
+use bevy::app::AppExit;
+use bevy::input::ButtonState;
+use bevy::input::keyboard::KeyboardInput;
+use bevy::log::{info, warn};
+use bevy::math::Vec2;
+use bevy::prelude::{EventReader, Has, KeyCode, Query, Res, Resource, Transform, Without};
+use bevy_rapier2d::prelude::Velocity;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use crate::bitmap::{BitmapTile, Mask};
+use crate::contour::Contour;
+use crate::debris::Debris;
+use crate::density::DensityChunk;
+use crate::item::{Inventory, Item, ItemKind};
+use crate::level::TerrainMaterial;
+use crate::mouth::Mouth;
+use crate::{Health, MAX_HEALTH, Player};
+use crate::polygon::{Polygon, Shape};
+
+pub(crate) const SAVE_VERSION: u32 = 3;
+pub(crate) const SAVE_PATH: &str = "save.ron";
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Resource, Serialize)]
+pub(crate) struct SaveGame {
+    pub(crate) version: u32,
+    pub(crate) terrain: Vec<TerrainSave>,
+    /// Local players in order of their `Player` index.
+    pub(crate) players: Vec<PlayerSave>,
+    #[serde(default)]
+    pub(crate) items: Vec<ItemSave>,
+    #[serde(default)]
+    pub(crate) inventory: Inventory,
+    #[serde(default)]
+    pub(crate) chunks: Vec<ChunkSave>,
+    #[serde(default)]
+    pub(crate) tiles: Vec<TileSave>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct TerrainSave {
+    pub(crate) polygon: Polygon,
+    pub(crate) transform: Transform,
+    #[serde(default)]
+    pub(crate) debris: bool,
+    #[serde(default)]
+    pub(crate) material: TerrainMaterial,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct ChunkSave {
+    pub(crate) chunk: DensityChunk,
+    pub(crate) transform: Transform,
+    pub(crate) material: TerrainMaterial,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct TileSave {
+    /// The tile's pixels, apron included.
+    pub(crate) mask: Mask,
+    pub(crate) transform: Transform,
+    pub(crate) material: TerrainMaterial,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct PlayerSave {
+    pub(crate) transform: Transform,
+    pub(crate) linvel: Vec2,
+    pub(crate) angvel: f32,
+    #[serde(default)]
+    pub(crate) mouth: Option<Mouth>,
+    #[serde(default = "full_health")]
+    pub(crate) health: f32,
+}
+
+fn full_health() -> f32 {
+    return MAX_HEALTH;
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct ItemSave {
+    pub(crate) kind: ItemKind,
+    pub(crate) position: Vec2,
+    pub(crate) revealed: bool,
+}
+
+/// Just enough of any version of the save file to tell which it is.
+#[derive(Deserialize)]
+struct SaveVersion {
+    version: u32,
+}
+
+/// Version 2, from before split-screen: a single player.
+#[derive(Deserialize)]
+struct SaveGameV2 {
+    terrain: Vec<TerrainSave>,
+    player: PlayerSave,
+    #[serde(default)]
+    items: Vec<ItemSave>,
+    #[serde(default)]
+    inventory: Inventory,
+}
+
+/// Version 1, from before mouths took a `Shape`: the mouth's outline is a bare `Polygon`.
+#[derive(Deserialize)]
+struct SaveGameV1 {
+    terrain: Vec<TerrainSave>,
+    player: PlayerSaveV1,
+    #[serde(default)]
+    items: Vec<ItemSave>,
+    #[serde(default)]
+    inventory: Inventory,
+}
+
+#[derive(Deserialize)]
+struct PlayerSaveV1 {
+    transform: Transform,
+    linvel: Vec2,
+    angvel: f32,
+    #[serde(default)]
+    mouth: Option<MouthV1>,
+}
+
+#[derive(Deserialize)]
+struct MouthV1 {
+    shape: Polygon,
+    offset: Vec2,
+    size: Vec2,
+}
+
+impl From<SaveGameV1> for SaveGameV2 {
+    fn from(save: SaveGameV1) -> Self {
+        let player = save.player;
+        SaveGameV2 {
+            terrain: save.terrain,
+            player: PlayerSave {
+                transform: player.transform,
+                linvel: player.linvel,
+                angvel: player.angvel,
+                mouth: player.mouth.map(|mouth| Mouth { shape: Shape::Polygon(mouth.shape), offset: mouth.offset, size: mouth.size }),
+                health: MAX_HEALTH,
+            },
+            items: save.items,
+            inventory: save.inventory,
+        }
+    }
+}
+
+impl From<SaveGameV2> for SaveGame {
+    fn from(save: SaveGameV2) -> Self {
+        SaveGame {
+            version: SAVE_VERSION,
+            terrain: save.terrain,
+            players: vec![save.player],
+            items: save.items,
+            inventory: save.inventory,
+            chunks: vec![],
+            tiles: vec![],
+        }
+    }
+}
+
+impl PlayerSave {
+    pub(crate) fn velocity(&self) -> Velocity {
+        return Velocity { linvel: self.linvel, angvel: self.angvel };
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum SaveError {
+    Io(io::Error),
+    Format(ron::Error),
+    Version(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "could not access save file: {error}"),
+            SaveError::Format(error) => write!(f, "could not parse save file: {error}"),
+            SaveError::Version(version) => write!(f, "save file version {version} is not supported (expected {SAVE_VERSION})"),
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(error: io::Error) -> Self {
+        SaveError::Io(error)
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(error: ron::Error) -> Self {
+        SaveError::Format(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for SaveError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        SaveError::Format(error.code)
+    }
+}
+
+impl SaveGame {
+    pub(crate) fn to_ron(&self) -> Result<String, SaveError> {
+        return Ok(ron::ser::to_string_pretty(self, PrettyConfig::default())?);
+    }
+
+    /// Parses a save file of this or any earlier version, migrating older ones forward.
+    pub(crate) fn from_ron(source: &str) -> Result<SaveGame, SaveError> {
+        let version = ron::from_str::<SaveVersion>(source)?.version;
+        return match version {
+            1 => Ok(SaveGame::from(SaveGameV2::from(ron::from_str::<SaveGameV1>(source)?))),
+            2 => Ok(SaveGame::from(ron::from_str::<SaveGameV2>(source)?)),
+            SAVE_VERSION => Ok(ron::from_str(source)?),
+            version => Err(SaveError::Version(version)),
+        };
+    }
+
+    pub(crate) fn write(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        return Ok(fs::write(path, self.to_ron()?)?);
+    }
+
+    pub(crate) fn read(path: impl AsRef<Path>) -> Result<SaveGame, SaveError> {
+        return SaveGame::from_ron(&fs::read_to_string(path)?);
+    }
+
+    /// Reads the save file at startup. A missing file starts a fresh game; any other failure is
+    /// logged and also starts a fresh game rather than refusing to launch.
+    pub(crate) fn load_or_default(path: impl AsRef<Path>) -> Option<SaveGame> {
+        return match SaveGame::read(path) {
+            Ok(save) => Some(save),
+            Err(SaveError::Io(error)) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => {
+                warn!("{error}");
+                None
+            }
+        };
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn update_save(
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut exit_events: EventReader<AppExit>,
+    inventory: Res<Inventory>,
+    terrain_query: Query<(&Polygon, &Transform, Has<Debris>, Option<&TerrainMaterial>), Without<Contour>>,
+    chunk_query: Query<(&DensityChunk, &Transform, &TerrainMaterial)>,
+    tile_query: Query<(&BitmapTile, &Transform, &TerrainMaterial)>,
+    player_query: Query<(&Player, &Transform, &Velocity, &Mouth, &Health)>,
+    item_query: Query<(&Item, &Transform)>,
+) {
+    let save_pressed = keyboard_events.read()
+        .any(|event| event.key_code == Some(KeyCode::F5) && event.state == ButtonState::Pressed);
+    let exiting = exit_events.read().count() > 0;
+    if !save_pressed && !exiting {
+        return;
+    }
+
+    let mut players: Vec<_> = player_query.iter().collect();
+    players.sort_by_key(|(player, _, _, _, _)| player.0);
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        terrain: terrain_query.iter()
+            .map(|(polygon, transform, debris, material)| TerrainSave {
+                polygon: polygon.clone(),
+                transform: *transform,
+                debris,
+                material: material.copied().unwrap_or_default(),
+            })
+            .collect(),
+        players: players.into_iter()
+            .map(|(_, transform, velocity, mouth, health)| PlayerSave {
+                transform: *transform,
+                linvel: velocity.linvel,
+                angvel: velocity.angvel,
+                mouth: Some(mouth.clone()),
+                health: health.0,
+            })
+            .collect(),
+        items: item_query.iter()
+            .map(|(item, transform)| ItemSave {
+                kind: item.kind,
+                position: transform.translation.truncate(),
+                revealed: item.revealed,
+            })
+            .collect(),
+        inventory: inventory.clone(),
+        chunks: chunk_query.iter()
+            .map(|(chunk, transform, material)| ChunkSave { chunk: chunk.clone(), transform: *transform, material: *material })
+            .collect(),
+        tiles: tile_query.iter()
+            .map(|(tile, transform, material)| TileSave { mask: tile.mask().clone(), transform: *transform, material: *material })
+            .collect(),
+    };
+
+    match save.write(SAVE_PATH) {
+        Ok(()) => info!("saved game to {SAVE_PATH}"),
+        Err(error) => warn!("{error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Quat, Vec2, Vec3};
+    use bevy::prelude::Transform;
+    use crate::bitmap::Mask;
+    use crate::density::DensityChunk;
+    use crate::item::{Inventory, ItemKind};
+    use crate::level::TerrainMaterial;
+    use crate::mouth::Mouth;
+    use crate::MAX_HEALTH;
+    use crate::polygon::{Polygon, Shape};
+    use crate::save::{ChunkSave, ItemSave, PlayerSave, SAVE_VERSION, SaveError, SaveGame, TerrainSave, TileSave};
+
+    fn save_game() -> SaveGame {
+        return SaveGame {
+            version: SAVE_VERSION,
+            terrain: vec![TerrainSave {
+                polygon: Polygon::from(vec![
+                    Vec2::new(-0.5, -0.5),
+                    Vec2::new(-0.5, 0.5),
+                    Vec2::new(0.1234567, 0.5),
+                    Vec2::new(0.5, -0.5),
+                ]),
+                transform: Transform::from_xyz(32., -32., 0.)
+                    .with_scale(Vec3::splat(64.)),
+                debris: false,
+                material: TerrainMaterial::Clay,
+            }],
+            players: vec![
+                PlayerSave {
+                    transform: Transform::from_xyz(-4., 4., 0.)
+                        .with_rotation(Quat::from_rotation_z(1.)),
+                    linvel: Vec2::new(16., -16.),
+                    angvel: 0.5,
+                    mouth: Some(Mouth { size: Vec2::new(6., 6.), ..Mouth::default() }),
+                    health: 37.5,
+                },
+                PlayerSave {
+                    transform: Transform::from_xyz(-4., -8., 0.),
+                    linvel: Vec2::ZERO,
+                    angvel: 0.,
+                    mouth: None,
+                    health: 100.,
+                },
+            ],
+            items: vec![ItemSave {
+                kind: ItemKind::Gem,
+                position: Vec2::new(8., -8.),
+                revealed: false,
+            }],
+            inventory: Inventory { score: 11, food: 1, gems: 1, power_ups: 0 },
+            chunks: vec![ChunkSave {
+                chunk: DensityChunk::from_polygon(&Polygon::rectangle(Vec2::splat(0.5)), 4),
+                transform: Transform::from_xyz(8., -8., 0.).with_scale(Vec3::splat(16.)),
+                material: TerrainMaterial::Dirt,
+            }],
+            tiles: vec![TileSave {
+                mask: Mask { width: 3, height: 3, solid: vec![false, false, false, false, true, false, false, false, false] },
+                transform: Transform::from_xyz(-8., -8., 0.),
+                material: TerrainMaterial::Rock,
+            }],
+        };
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let expected = save_game();
+
+        let actual = SaveGame::from_ron(&expected.to_ron().unwrap()).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_round_trip_file() {
+        let expected = save_game();
+        let path = "target/test_round_trip_file.ron";
+
+        expected.write(path).unwrap();
+        let actual = SaveGame::read(path).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_missing_items_default() {
+        let mut expected = save_game();
+        expected.items = vec![];
+        expected.inventory = Inventory::default();
+        let source = ron::to_string(&expected).unwrap()
+            .replace(",items:[],inventory:(score:0,food:0,gems:0,power_ups:0)", "");
+
+        let actual = SaveGame::from_ron(&source).unwrap();
+
+        assert!(!source.contains("inventory"));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_migrate_v2() {
+        let source = "(version: 2, terrain: [], player: (transform: (translation: (-4, 4, 0), rotation: (0, 0, 0, 1), scale: (1, 1, 1)), linvel: (1, 0), angvel: 0, mouth: Some((shape: Rectangle((1, 1)), offset: (5, 0), size: (4, 4)))))";
+
+        let actual = SaveGame::from_ron(source).unwrap();
+
+        assert_eq!(actual.version, SAVE_VERSION);
+        assert_eq!(actual.players.len(), 1);
+        assert_eq!(actual.players[0].linvel, Vec2::new(1., 0.));
+        assert_eq!(actual.players[0].mouth, Some(Mouth { offset: Vec2::new(5., 0.), ..Mouth::default() }));
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        let source = "(version: 1, terrain: [], player: (transform: (translation: (-4, 4, 0), rotation: (0, 0, 0, 1), scale: (1, 1, 1)), linvel: (0, 0), angvel: 0, mouth: Some((shape: (vertices: [(0, 0), (0, 1), (1, 0)]), offset: (4, 0), size: (4, 4)))))";
+
+        let actual = SaveGame::from_ron(source).unwrap();
+
+        assert_eq!(actual.players.len(), 1);
+        assert_eq!(actual.players[0].health, MAX_HEALTH);
+        assert_eq!(
+            actual.players[0].mouth.as_ref().map(|mouth| mouth.shape.clone()),
+            Some(Shape::Polygon(Polygon::from(vec![Vec2::ZERO, Vec2::Y, Vec2::X]))),
+        );
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut save = save_game();
+        save.version = SAVE_VERSION + 1;
+
+        let actual = SaveGame::from_ron(&save.to_ron().unwrap());
+
+        assert!(matches!(actual, Err(SaveError::Version(version)) if version == SAVE_VERSION + 1));
+    }
+}
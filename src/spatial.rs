@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use bevy::math::{IVec2, Rect};
+use bevy::prelude::{DetectChanges, Entity, Query, Ref, RemovedComponents, ResMut, Resource, Transform};
+use crate::polygon::Polygon;
+
+/// Side of a `TerrainIndex` cell, in world units.
+const INDEX_CELL_SIZE: f32 = 16.;
+
+/// Broad phase over the global-space AABBs of terrain polygons: a uniform grid mapping each cell to
+/// the entities whose AABB overlaps it.
+#[derive(Resource)]
+pub(crate) struct TerrainIndex {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<Entity>>,
+    aabbs: HashMap<Entity, Rect>,
+}
+
+impl Default for TerrainIndex {
+    fn default() -> Self {
+        TerrainIndex::new(INDEX_CELL_SIZE)
+    }
+}
+
+impl TerrainIndex {
+    pub(crate) fn new(cell_size: f32) -> Self {
+        TerrainIndex {
+            cell_size,
+            cells: HashMap::new(),
+            aabbs: HashMap::new(),
+        }
+    }
+
+    fn cells(&self, aabb: Rect) -> impl Iterator<Item = IVec2> {
+        let min = (aabb.min / self.cell_size).floor().as_ivec2();
+        let max = (aabb.max / self.cell_size).floor().as_ivec2();
+        return (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)));
+    }
+
+    pub(crate) fn insert(&mut self, entity: Entity, aabb: Rect) {
+        self.remove(entity);
+        for cell in self.cells(aabb).collect::<Vec<IVec2>>() {
+            self.cells.entry(cell).or_default().push(entity);
+        }
+        self.aabbs.insert(entity, aabb);
+    }
+
+    pub(crate) fn remove(&mut self, entity: Entity) {
+        let Some(aabb) = self.aabbs.remove(&entity) else {
+            return;
+        };
+        for cell in self.cells(aabb).collect::<Vec<IVec2>>() {
+            if let Some(entities) = self.cells.get_mut(&cell) {
+                entities.retain(|other| *other != entity);
+                if entities.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn aabb(&self, entity: Entity) -> Option<Rect> {
+        return self.aabbs.get(&entity).copied();
+    }
+
+    /// Entities whose AABB overlaps `region`, each once, in a stable order.
+    pub(crate) fn query(&self, region: Rect) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = self.cells(region)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(|entity| overlaps(self.aabbs[entity], region))
+            .collect();
+        entities.sort();
+        entities.dedup();
+        return entities;
+    }
+}
+
+/// Whether two rectangles overlap or touch.
+pub(crate) fn overlaps(a: Rect, b: Rect) -> bool {
+    return a.min.cmple(b.max).all() && b.min.cmple(a.max).all();
+}
+
+/// Re-indexes terrain whose outline or placement changed and forgets terrain that is gone.
+pub(crate) fn update_terrain_index(
+    mut index: ResMut<TerrainIndex>,
+    terrain_query: Query<(Entity, Ref<Polygon>, Ref<Transform>)>,
+    mut removed: RemovedComponents<Polygon>,
+) {
+    for entity in removed.read() {
+        index.remove(entity);
+    }
+    for (entity, polygon, transform) in terrain_query.iter() {
+        if !polygon.is_changed() && !transform.is_changed() {
+            continue;
+        }
+        index.insert(entity, polygon.to_global_space(&transform).aabb());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Rect;
+    use bevy::prelude::Entity;
+    use crate::spatial::TerrainIndex;
+
+    #[test]
+    fn test_query_nearby() {
+        let mut index = TerrainIndex::new(16.);
+        let near = Entity::from_raw(1);
+        let far = Entity::from_raw(2);
+        let large = Entity::from_raw(3);
+        index.insert(near, Rect::new(0., 0., 4., 4.));
+        index.insert(far, Rect::new(100., 100., 104., 104.));
+        index.insert(large, Rect::new(-64., -64., 64., 64.));
+
+        assert_eq!(index.query(Rect::new(2., 2., 6., 6.)), vec![near, large]);
+        assert_eq!(index.query(Rect::new(90., 90., 101., 101.)), vec![far]);
+    }
+
+    #[test]
+    fn test_reinsert_moves_entry() {
+        let mut index = TerrainIndex::new(16.);
+        let entity = Entity::from_raw(1);
+        index.insert(entity, Rect::new(0., 0., 4., 4.));
+
+        index.insert(entity, Rect::new(40., 0., 44., 4.));
+
+        assert!(index.query(Rect::new(0., 0., 4., 4.)).is_empty());
+        assert_eq!(index.query(Rect::new(40., 0., 41., 1.)), vec![entity]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut index = TerrainIndex::new(16.);
+        let entity = Entity::from_raw(1);
+        index.insert(entity, Rect::new(0., 0., 40., 4.));
+
+        index.remove(entity);
+
+        assert!(index.query(Rect::new(0., 0., 40., 4.)).is_empty());
+        assert!(index.cells.is_empty());
+    }
+}
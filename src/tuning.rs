@@ -0,0 +1,148 @@
+use bevy::asset::{Asset, AssetEvent, AssetLoader, AssetServer, Assets, AsyncReadExt, Handle, LoadContext};
+use bevy::asset::io::Reader;
+use bevy::math::Vec2;
+use bevy::prelude::{Commands, EventReader, OrthographicProjection, Query, Res, ResMut, Resource, With};
+use bevy::reflect::TypePath;
+use bevy::utils::BoxedFuture;
+use bevy_rapier2d::geometry::Collider;
+use bevy_rapier2d::prelude::GravityScale;
+use serde::{Deserialize, Serialize};
+use crate::asset::AssetError;
+use crate::camera::PlayerCamera;
+use crate::polygon_transform_bundle::Precision;
+use crate::Player;
+
+pub(crate) const TUNING_ASSET_PATH: &str = "game.tuning.ron";
+
+/// Numbers that decide how the game feels, read from `TUNING_ASSET_PATH` and reloaded whenever the
+/// file changes. Until it loads, and for anything it leaves out, the defaults apply.
+#[derive(Asset, Clone, Debug, Deserialize, PartialEq, Resource, Serialize, TypePath)]
+#[serde(default)]
+pub(crate) struct Tuning {
+    /// Worm speed, in world units per second.
+    pub(crate) player_speed: f32,
+    /// Worm turning speed, in radians per second.
+    pub(crate) player_turn_speed: f32,
+    /// Half extents of a worm's collider.
+    pub(crate) worm_size: Vec2,
+    /// Moves every worm's mouth on top of its own offset, so reach can be tuned without touching
+    /// mouth definitions or upgrades.
+    pub(crate) mouth_offset: Vec2,
+    /// Starting scale of each player's camera, in world units per pixel.
+    pub(crate) camera_scale: f32,
+    pub(crate) gravity_scale: f32,
+    /// Tolerances for carving terrain.
+    pub(crate) precision: Precision,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            player_speed: 16.,
+            player_turn_speed: 4.,
+            worm_size: Vec2::new(2., 2.),
+            mouth_offset: Vec2::ZERO,
+            camera_scale: 1. / 16.,
+            gravity_scale: 0.,
+            precision: Precision::default(),
+        }
+    }
+}
+
+impl Tuning {
+    pub(crate) fn from_ron(source: &str) -> Result<Tuning, AssetError> {
+        return Ok(ron::from_str(source)?);
+    }
+
+    pub(crate) fn collider(&self) -> Collider {
+        return Collider::cuboid(self.worm_size.x, self.worm_size.y);
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct TuningLoader;
+
+impl AssetLoader for TuningLoader {
+    type Asset = Tuning;
+    type Settings = ();
+    type Error = AssetError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Tuning, AssetError>> {
+        return Box::pin(async move {
+            let mut source = String::new();
+            reader.read_to_string(&mut source).await?;
+            return Tuning::from_ron(&source);
+        });
+    }
+
+    fn extensions(&self) -> &[&str] {
+        return &["tuning.ron"];
+    }
+}
+
+/// Keeps the tuning file loaded so changes to it are picked up.
+#[derive(Resource)]
+pub(crate) struct TuningHandle(Handle<Tuning>);
+
+pub(crate) fn startup_tuning(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(TuningHandle(asset_server.load(TUNING_ASSET_PATH)));
+}
+
+/// Copies the tuning file into the `Tuning` resource whenever it loads or changes, and updates
+/// what was already spawned with the old numbers. Speeds and the mouth offset are read each frame.
+pub(crate) fn update_tuning(
+    mut tuning_events: EventReader<AssetEvent<Tuning>>,
+    handle: Res<TuningHandle>,
+    tunings: Res<Assets<Tuning>>,
+    mut tuning: ResMut<Tuning>,
+    mut worm_query: Query<(&mut Collider, &mut GravityScale), With<Player>>,
+    mut camera_query: Query<&mut OrthographicProjection, With<PlayerCamera>>,
+) {
+    let changed = tuning_events.read()
+        .any(|event| event.is_loaded_with_dependencies(&handle.0) || event.is_modified(&handle.0));
+    let Some(loaded) = tunings.get(&handle.0).filter(|_| changed) else {
+        return;
+    };
+    if *loaded == *tuning {
+        return;
+    }
+
+    if loaded.worm_size != tuning.worm_size || loaded.gravity_scale != tuning.gravity_scale {
+        for (mut collider, mut gravity_scale) in worm_query.iter_mut() {
+            *collider = loaded.collider();
+            gravity_scale.0 = loaded.gravity_scale;
+        }
+    }
+    // Only a changed scale resets the zoom, so retuning something else leaves it alone.
+    if loaded.camera_scale != tuning.camera_scale {
+        for mut projection in camera_query.iter_mut() {
+            projection.scale = loaded.camera_scale;
+        }
+    }
+    *tuning = loaded.clone();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::polygon_transform_bundle::Precision;
+    use crate::tuning::Tuning;
+
+    #[test]
+    fn test_missing_fields_default() {
+        let actual = Tuning::from_ron("(player_speed: 24)").unwrap();
+
+        assert_eq!(actual, Tuning { player_speed: 24., ..Tuning::default() });
+    }
+
+    #[test]
+    fn test_precision() {
+        let actual = Tuning::from_ron("(precision: (snap: 0.25))").unwrap();
+
+        assert_eq!(actual.precision, Precision { snap: 0.25, ..Precision::default() });
+    }
+}